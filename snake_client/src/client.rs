@@ -1,14 +1,15 @@
 //use crate::game::*;
 //use crate::snake::{Direction, Snake, Point};
-use crate::shared_structures::ClientMsg::*;
-use crate::shared_structures::Direction::*;
-use crate::shared_structures::*;
-use crate::shared_structures::{Game, PlayerStatus, Point, Snake};
 use crate::{log_in_file, LOG_FILE};
+use snake_protocol::ClientMsg::*;
+use snake_protocol::Direction::*;
+use snake_protocol::*;
+use snake_protocol::{Game, PlayerStatus, Point, Snake};
+use std::collections::VecDeque;
 use std::fs::File;
 use std::io::{stdout, Read, Stdout, Write};
 use termion::raw::{IntoRawMode, RawTerminal};
-use termion::{async_stdin, clear, color, cursor, AsyncReader};
+use termion::{async_stdin, clear, color, cursor, terminal_size, AsyncReader};
 
 pub const FOOD_CHAR: char = 'Ծ';
 const MARGIN_AFTER_FIELD: u16 = 4;
@@ -17,32 +18,88 @@ const PLAYER_1_CONTROLS: [u8; 4] = [b'q', b'z', b'd', b's'];
 const PLAYER_2_CONTROLS: [u8; 4] = [b'j', b'i', b'l', b'k'];
 
 const PLAYERS_CONTROLS: [[u8; 4]; 2] = [PLAYER_1_CONTROLS, PLAYER_2_CONTROLS];
-const PLAYERS_COLORS: [&dyn color::Color; 4] =
-    [&color::Red, &color::Blue, &color::Green, &color::Yellow];
+// Touches de la salle d'attente
+const KEY_ESC: u8 = 27;
+// Ctrl-C n'envoie pas de signal en mode raw, il quitte comme Esc
+const KEY_INTERRUPT: u8 = 3;
+const KEY_READY: u8 = b' ';
+const KEY_START: u8 = b'\r';
+// Touches du chat, pendant la saisie les autres touches sont écrites dans le message
+const KEY_CHAT: u8 = b't';
+const KEY_SEND: u8 = b'\r';
+const KEY_BACKSPACE: u8 = 127;
+// Nombre de messages du chat affichés
+const CHAT_LINES: usize = 5;
+// Chaque serpent a une combinaison unique de couleur et de forme de corps
+const PLAYERS_COLORS: [&dyn color::Color; 8] = [
+    &color::Red,
+    &color::Blue,
+    &color::Green,
+    &color::Yellow,
+    &color::Magenta,
+    &color::Cyan,
+    &color::White,
+    &color::LightBlack,
+];
+const BODY_CHARS: [char; 2] = ['▪', '•'];
 // Specific to my terminal where the length of 1 unit of the x axis is half of 1 unit of the y axis
 const X_RATIO: u16 = 2;
 
-#[inline]
-fn client_width() -> u16 {
-    X_RATIO * WIDTH as u16
-}
-
 pub trait Drawer {
     fn init(nb_players: u32, serpents: Vec<u32>) -> Self;
+    fn draw_lobby(&mut self, lobby: &Lobby);
     fn draw_game(&mut self, game: &Game);
     fn draw_error(&mut self);
-    fn draw_end(&mut self, winner: Option<u32>);
+    fn draw_end(&mut self, winner: Option<Winner>);
 }
 
 pub struct Termion {
     nb_players: u32,
     snakes_nb: Vec<u32>,
+    // Dimensions du terrain annoncées par le serveur
+    width: u16,
+    height: u16,
+    // Le client s'est déclaré prêt dans la salle d'attente
+    ready: bool,
+    // Numéro de la dernière commande envoyée au serveur
+    sequence: u64,
+    // Derniers messages du chat et ligne du terminal où ils sont affichés
+    chat: VecDeque<ChatMessage>,
+    chat_top: u16,
+    // Message en cours de saisie, None si les touches dirigent les serpents
+    typing: Option<Vec<u8>>,
+    // Arrivé pendant une partie sans place libre, le client regarde la
+    // partie en attendant ses serpents
+    waiting: bool,
+    // Résumé affiché en quittant: dernier état de la partie, nombre de
+    // parties terminées et gagnées par un serpent du client
+    last_game: Option<Game>,
+    games: u32,
+    wins: u32,
+    // Le terminal a retrouvé son mode d'origine
+    restored: bool,
     stdin: AsyncReader,
     stdout: RawTerminal<Stdout>,
 }
 
 pub fn max_players_on_terminal() -> u32 {
-    std::cmp::max(PLAYERS_COLORS.len(), PLAYERS_CONTROLS.len()) as u32
+    PLAYERS_CONTROLS.len() as u32
+}
+
+fn snake_color(snake_id: u32) -> &'static dyn color::Color {
+    PLAYERS_COLORS[(snake_id as usize - 1) % PLAYERS_COLORS.len()]
+}
+
+fn body_char(snake_id: u32) -> char {
+    BODY_CHARS[(snake_id as usize - 1) / PLAYERS_COLORS.len() % BODY_CHARS.len()]
+}
+
+fn score_msg(status: &PlayerStatus) -> String {
+    match status {
+        PlayerStatus::Leaver => "Left the game".to_owned(),
+        PlayerStatus::Loser => "Lost".to_owned(),
+        PlayerStatus::Player(score) => score.to_string(),
+    }
 }
 
 impl Drawer for Termion {
@@ -53,29 +110,136 @@ impl Drawer for Termion {
         Termion {
             nb_players,
             snakes_nb: serpents,
+            width: 0,
+            height: 0,
+            ready: false,
+            sequence: 0,
+            chat: VecDeque::new(),
+            chat_top: MARGIN_TOP,
+            typing: None,
+            waiting: false,
+            last_game: None,
+            games: 0,
+            wins: 0,
+            restored: false,
             stdin,
             stdout,
         }
     }
 
+    fn draw_lobby(&mut self, lobby: &Lobby) {
+        let taken: usize = lobby.players.iter().map(|p| p.snakes.len()).sum();
+        let is_host = lobby
+            .players
+            .iter()
+            .any(|p| p.host && p.snakes == self.snakes_nb);
+        let mut lines = vec![
+            format!("# Room \"{}\"", lobby.room),
+            String::new(),
+            format!("Players: {}/{}", taken, lobby.seats),
+        ];
+        if lobby.spectators > 0 {
+            lines.push(format!("Spectators: {}", lobby.spectators));
+        }
+        for player in lobby.players.iter() {
+            let snakes: Vec<String> = player
+                .snakes
+                .iter()
+                .enumerate()
+                .map(|(i, snake)| {
+                    format!(
+                        "{}{} {}{}",
+                        color::Fg(snake_color(*snake)),
+                        body_char(*snake),
+                        player
+                            .names
+                            .get(i)
+                            .cloned()
+                            .unwrap_or_else(|| default_name(*snake)),
+                        color::Fg(color::Reset)
+                    )
+                })
+                .collect();
+            lines.push(format!(
+                "- {}: {}{}{}",
+                snakes.join(", "),
+                if player.ready { "ready" } else { "not ready" },
+                if player.host { " (host)" } else { "" },
+                if player.snakes == self.snakes_nb {
+                    " (you)"
+                } else {
+                    ""
+                }
+            ));
+        }
+        lines.push(String::new());
+        // Un joueur arrivé en retard est assis à la manche suivante, il n'est
+        // pas spectateur
+        if self.waiting {
+            lines.push(String::from("You are waiting for the next round"));
+            lines.push(String::from("You will play as soon as it starts"));
+        } else if self.is_spectator() {
+            lines.push(String::from("You are watching this room"));
+        } else {
+            lines.push(String::from("Space: ready / not ready"));
+        }
+        if is_host {
+            lines.push(String::from("Enter: start now, free seats go to bots"));
+        } else {
+            lines.push(String::from("The game starts when all seats are taken"));
+            lines.push(String::from("and all players are ready"));
+        }
+        lines.push(String::from("Chat: \"t\""));
+        lines.push(String::from("Quit: \"Esc\""));
+
+        write!(self.stdout, "{}", clear::All).unwrap();
+        for (i, line) in lines.iter().enumerate() {
+            write!(
+                self.stdout,
+                "{}{}",
+                cursor::Goto(1, MARGIN_TOP + i as u16),
+                line
+            )
+            .unwrap();
+        }
+        self.chat_top = MARGIN_TOP + lines.len() as u16 + 1;
+        self.draw_chat();
+    }
+
     fn draw_game(&mut self, game: &Game) {
-        self.draw_field(client_width(), HEIGHT as u16);
-        for snake in game.snakes.iter() {
-            if let Some(snake) = snake {
-                self.draw_snake(snake);
-            }
+        self.width = game.width;
+        self.height = game.height;
+        self.draw_field(self.client_width(), self.height);
+        for snake in game.snakes.iter().flatten() {
+            self.draw_snake(snake);
         }
         self.draw_food(&game.food);
-        let current_y = self.draw_scores(&game);
-        let _current_y = self.draw_instructions(current_y, game.points_to_win);
+        let current_y = self.draw_scores(game);
+        let _current_y = self.draw_instructions(current_y, game);
+        // Sous la ligne réservée aux problèmes de connexion
+        self.chat_top = self.height + 2;
+        self.draw_chat();
         self.cursor_at_bottom();
+        match &mut self.last_game {
+            Some(last_game) => last_game.clone_from(game),
+            None => self.last_game = Some(game.clone()),
+        }
     }
 
     // Met le curseur en bas
-    fn draw_end(&mut self, winner: Option<u32>) {
+    fn draw_end(&mut self, winner: Option<Winner>) {
+        self.games += 1;
+        if winner
+            .as_ref()
+            .is_some_and(|winner| self.snakes_nb.contains(&winner.snake))
+        {
+            self.wins += 1;
+        }
+        // Un spectateur arrivé au dernier tour n'a pas encore de terrain
         match winner {
+            _ if self.height == 0 => (),
             None => self.draw_draw(),
-            Some(winner) => self.draw_winner(winner),
+            Some(winner) => self.draw_winner(&winner),
         }
         self.cursor_at_bottom();
     }
@@ -84,55 +248,215 @@ impl Drawer for Termion {
 }
 
 impl Termion {
+    #[inline]
+    fn client_width(&self) -> u16 {
+        X_RATIO * self.width
+    }
+
+    fn is_spectator(&self) -> bool {
+        self.nb_players == 0
+    }
+
+    pub fn wait_for_seat(&mut self) {
+        self.waiting = true;
+    }
+
+    // Serpents donnés par le serveur à la fin de l'attente
+    pub fn seat(&mut self, serpents: Vec<u32>) {
+        self.nb_players = serpents.len() as u32;
+        self.snakes_nb = serpents;
+        self.waiting = false;
+    }
+
     pub fn get_inputs(&mut self) -> ClientMsg {
         //On lit 10 caractères
         let mut buffer = [0; 10];
-        self.stdin.read(&mut buffer).unwrap();
-        let mut v = vec![None; self.nb_players as usize];
+        let read = self.stdin.read(&mut buffer).unwrap();
+        // Toutes les touches sont gardées, le serveur applique un virage par tour
+        let mut v = vec![vec![]; self.nb_players as usize];
 
-        for c in buffer.iter() {
+        for c in buffer[..read].iter() {
+            if *c == KEY_INTERRUPT {
+                return Leave(self.nb_players);
+            }
+            if self.typing.is_some() {
+                if let Some(msg) = self.type_chat(*c) {
+                    return msg;
+                }
+                continue;
+            }
+            if *c == KEY_CHAT {
+                self.start_chat();
+                continue;
+            }
             for i in 0..self.nb_players as usize {
                 match PLAYERS_CONTROLS[i].iter().position(|control| control == c) {
                     None => (),
                     Some(pos) => match pos {
-                        0 => v[i] = Some(Left),
-                        1 => v[i] = Some(Up),
-                        2 => v[i] = Some(Right),
-                        3 => v[i] = Some(Down),
+                        0 => v[i].push(Left),
+                        1 => v[i].push(Up),
+                        2 => v[i].push(Right),
+                        3 => v[i].push(Down),
                         _ => panic!("Should not happen"),
                     },
                 }
             }
             // Quitter la partie avec la touch Esc
-            if *c == 27 {
+            if *c == KEY_ESC {
                 return Leave(self.nb_players);
             }
         }
         log_in_file(format!("{:?}\n", v));
-        SnakeDirection(v)
+        // Seules les commandes envoyées au serveur sont numérotées
+        if v.iter().any(|directions| !directions.is_empty()) {
+            self.sequence += 1;
+        }
+        SnakeDirection(self.sequence, v)
+    }
+
+    // Commandes de la salle d'attente, dans l'ordre des touches
+    pub fn get_lobby_inputs(&mut self) -> Vec<ClientMsg> {
+        let mut buffer = [0; 10];
+        let read = self.stdin.read(&mut buffer).unwrap();
+        let mut msgs = vec![];
+        for c in buffer[..read].iter() {
+            if *c == KEY_INTERRUPT {
+                return vec![Leave(self.nb_players)];
+            }
+            if self.typing.is_some() {
+                msgs.extend(self.type_chat(*c));
+                continue;
+            }
+            match *c {
+                KEY_ESC => return vec![Leave(self.nb_players)],
+                KEY_CHAT => self.start_chat(),
+                // Un spectateur peut seulement quitter
+                _ if self.is_spectator() => (),
+                KEY_READY => {
+                    self.ready = !self.ready;
+                    msgs.push(Ready(self.ready));
+                }
+                KEY_START => msgs.push(Start),
+                _ => (),
+            }
+        }
+        msgs
+    }
+
+    // Les touches suivantes sont écrites dans un message du chat
+    fn start_chat(&mut self) {
+        self.typing = Some(vec![]);
+        self.draw_chat();
     }
 
-    fn draw_instructions(&mut self, mut current_y: u16, points_to_win: u32) -> u16 {
+    // Ajoute une touche au message en cours, retourne le message quand il est
+    // envoyé. Esc abandonne le message sans quitter la partie
+    fn type_chat(&mut self, c: u8) -> Option<ClientMsg> {
+        let typing = self.typing.as_mut()?;
+        let mut msg = None;
+        match c {
+            KEY_SEND => {
+                msg = chat_text(&String::from_utf8_lossy(typing)).map(Chat);
+                self.typing = None;
+            }
+            KEY_ESC => self.typing = None,
+            // Retire le dernier caractère, qui peut tenir sur plusieurs octets
+            KEY_BACKSPACE => while typing.pop().is_some_and(|byte| byte & 0xC0 == 0x80) {},
+            _ if c < b' ' => (),
+            _ if String::from_utf8_lossy(typing).chars().count() >= MAX_CHAT_LEN => (),
+            _ => typing.push(c),
+        }
+        self.draw_chat();
+        msg
+    }
+
+    // Nouveau message du chat relayé par le serveur
+    pub fn add_chat(&mut self, msg: ChatMessage) {
+        self.chat.push_back(msg);
+        if self.chat.len() > CHAT_LINES {
+            self.chat.pop_front();
+        }
+        self.draw_chat();
+    }
+
+    // Derniers messages du chat, chacun dans la couleur de son auteur,
+    // suivis du message en cours de saisie
+    fn draw_chat(&mut self) {
+        let width = terminal_size().map_or(80, |(width, _)| width as usize);
+        for i in 0..CHAT_LINES {
+            write!(
+                self.stdout,
+                "{}{}",
+                cursor::Goto(1, self.chat_top + i as u16),
+                clear::CurrentLine
+            )
+            .unwrap();
+            let msg = match self.chat.get(i) {
+                Some(msg) => msg,
+                None => continue,
+            };
+            let (author_color, author) = match msg.snakes.first() {
+                None => (
+                    &color::Reset as &dyn color::Color,
+                    String::from("Spectator"),
+                ),
+                Some(snake) => (
+                    snake_color(*snake),
+                    format!("{} {}", body_char(*snake), msg.author),
+                ),
+            };
+            // Une ligne trop longue ne doit pas déborder sur la suivante
+            let text: String = msg
+                .text
+                .chars()
+                .take(width.saturating_sub(author.chars().count() + 2))
+                .collect();
+            write!(
+                self.stdout,
+                "{}{}{}: {}",
+                color::Fg(author_color),
+                author,
+                color::Fg(color::Reset),
+                text
+            )
+            .unwrap();
+        }
+        let prompt = match &self.typing {
+            Some(typing) => format!("> {}_", String::from_utf8_lossy(typing)),
+            None => String::new(),
+        };
+        write!(
+            self.stdout,
+            "{}{}{}",
+            cursor::Goto(1, self.chat_top + CHAT_LINES as u16),
+            clear::CurrentLine,
+            prompt.chars().take(width).collect::<String>()
+        )
+        .unwrap();
+        self.stdout.flush().unwrap();
+    }
+
+    fn draw_instructions(&mut self, mut current_y: u16, game: &Game) -> u16 {
         current_y += 2;
         write!(
             self.stdout,
             "{}#How to win:",
-            cursor::Goto(client_width() + MARGIN_AFTER_FIELD, current_y)
+            cursor::Goto(self.client_width() + MARGIN_AFTER_FIELD, current_y)
         )
         .unwrap();
         current_y += 1;
         write!(
             self.stdout,
             "{}- Last survivor",
-            cursor::Goto(client_width() + MARGIN_AFTER_FIELD, current_y)
+            cursor::Goto(self.client_width() + MARGIN_AFTER_FIELD, current_y)
         )
         .unwrap();
         current_y += 1;
         write!(
             self.stdout,
             "{}- First to reach {}",
-            cursor::Goto(client_width() + MARGIN_AFTER_FIELD, current_y),
-            points_to_win
+            cursor::Goto(self.client_width() + MARGIN_AFTER_FIELD, current_y),
+            game.points_to_win
         )
         .unwrap();
 
@@ -140,26 +464,32 @@ impl Termion {
         write!(
             self.stdout,
             "{}# Controls",
-            cursor::Goto(client_width() + MARGIN_AFTER_FIELD, current_y)
+            cursor::Goto(self.client_width() + MARGIN_AFTER_FIELD, current_y)
         )
         .unwrap();
         current_y += 1;
         write!(
             self.stdout,
             "{}Quit: \"Esc\"",
-            cursor::Goto(client_width() + MARGIN_AFTER_FIELD, current_y)
+            cursor::Goto(self.client_width() + MARGIN_AFTER_FIELD, current_y)
+        )
+        .unwrap();
+        current_y += 1;
+        write!(
+            self.stdout,
+            "{}Chat: \"t\"",
+            cursor::Goto(self.client_width() + MARGIN_AFTER_FIELD, current_y)
         )
         .unwrap();
 
         for (i, player) in self.snakes_nb.iter().enumerate() {
-            let player_index = (*player - 1) as usize;
             current_y += 1;
             write!(
                 self.stdout,
-                "{}{}Snake {}: {:?}{}",
-                cursor::Goto(client_width() + MARGIN_AFTER_FIELD, current_y),
-                color::Fg(PLAYERS_COLORS[player_index]),
-                player,
+                "{}{}{}: {:?}{}",
+                cursor::Goto(self.client_width() + MARGIN_AFTER_FIELD, current_y),
+                color::Fg(snake_color(*player)),
+                game.name(*player),
                 PLAYERS_CONTROLS[i]
                     .iter()
                     .fold(String::from(""), |acc, c| format!("{}{}", acc, *c as char)),
@@ -172,14 +502,14 @@ impl Termion {
         write!(
             self.stdout,
             "{}Note: the game restart",
-            cursor::Goto(client_width() + MARGIN_AFTER_FIELD, current_y)
+            cursor::Goto(self.client_width() + MARGIN_AFTER_FIELD, current_y)
         )
         .unwrap();
         current_y += 1;
         write!(
             self.stdout,
             "{}automatically at the end",
-            cursor::Goto(client_width() + MARGIN_AFTER_FIELD, current_y)
+            cursor::Goto(self.client_width() + MARGIN_AFTER_FIELD, current_y)
         )
         .unwrap();
 
@@ -232,12 +562,11 @@ impl Termion {
     }
 
     fn draw_snake(&mut self, snake: &Snake) {
-        let snake_index = snake.id - 1;
         write!(
             self.stdout,
             "{}{}{}",
             cursor::Goto(snake.head.x * X_RATIO, snake.head.y),
-            color::Fg(PLAYERS_COLORS[snake_index as usize]),
+            color::Fg(snake_color(snake.id)),
             Termion::head_char(snake.direction.clone())
         )
         .unwrap();
@@ -247,7 +576,7 @@ impl Termion {
                 self.stdout,
                 "{}{}",
                 cursor::Goto(snake.body[i].x * X_RATIO, snake.body[i].y),
-                Termion::BODY_CHAR
+                body_char(snake.id)
             )
             .unwrap();
         }
@@ -269,93 +598,142 @@ impl Termion {
     }
 
     fn cursor_at_bottom(&mut self) {
-        write!(self.stdout, "{}", cursor::Goto(0, HEIGHT as u16 + 1)).unwrap();
+        write!(self.stdout, "{}", cursor::Goto(0, self.height + 1)).unwrap();
         self.stdout.flush().unwrap();
     }
 
-    fn draw_draw(&mut self) {
-        let w: u16 = client_width();
-        let h: u16 = HEIGHT as u16;
+    // Efface l'écran et rend le terminal dans son état d'origine, puis
+    // affiche la raison du départ et le résumé des parties
+    pub fn close(mut self, reason: &str) {
+        let _ = write!(self.stdout, "{}{}", clear::All, cursor::Goto(1, 1));
+        self.restore();
+        println!("{}", reason);
+        if self.is_spectator() {
+            println!("Games watched: {}", self.games);
+        } else {
+            println!("Games played: {}, won: {}", self.games, self.wins);
+        }
+        if let Some(game) = &self.last_game {
+            println!("Last scores:");
+            for (i, score) in game.scores.iter().enumerate() {
+                let snake = i as u32 + 1;
+                println!(
+                    "  {}: {}{}",
+                    game.name(snake),
+                    score_msg(score),
+                    if self.snakes_nb.contains(&snake) {
+                        " (you)"
+                    } else {
+                        ""
+                    }
+                );
+            }
+        }
+    }
+
+    // Remet les couleurs et le curseur puis quitte le mode raw
+    fn restore(&mut self) {
+        self.restored = true;
+        let _ = write!(self.stdout, "{}{}", color::Fg(color::Reset), cursor::Show);
+        let _ = self.stdout.flush();
+        let _ = self.stdout.suspend_raw_mode();
+    }
+
+    pub fn draw_connection_lost(&mut self) {
         write!(
             self.stdout,
-            "{}-------------------",
-            cursor::Goto((w / 2) - 10, h / 2 - 1)
+            "{}{}Connection lost, reconnecting...",
+            cursor::Goto(1, self.height + 1),
+            clear::CurrentLine
         )
         .unwrap();
+        self.stdout.flush().unwrap();
+    }
+
+    fn draw_draw(&mut self) {
+        let x = (self.client_width() / 2).saturating_sub(10).max(1);
+        let y = (self.height / 2).saturating_sub(1).max(1);
+        write!(self.stdout, "{}-------------------", cursor::Goto(x, y)).unwrap();
         write!(
             self.stdout,
             "{}|       DRAW!      |",
-            cursor::Goto((w / 2) - 10, h / 2)
-        )
-        .unwrap();
-        write!(
-            self.stdout,
-            "{}-------------------",
-            cursor::Goto((w / 2) - 10, h / 2 + 1)
+            cursor::Goto(x, y + 1)
         )
         .unwrap();
+        write!(self.stdout, "{}-------------------", cursor::Goto(x, y + 2)).unwrap();
         self.stdout.flush().unwrap();
     }
 
-    fn draw_winner(&mut self, winner: u32) {
-        let w: u16 = client_width();
-        let h: u16 = HEIGHT as u16;
+    fn draw_winner(&mut self, winner: &Winner) {
+        let w: u16 = self.client_width();
+        let h: u16 = self.height;
+        // Le cadre s'adapte à la longueur du surnom
+        let msg = format!("|    {} WINS!    |", winner.name);
+        let len = msg.chars().count() as u16;
+        let x = (w / 2).saturating_sub(len / 2).max(1);
+        let y = (h / 2).saturating_sub(1).max(1);
+        let border = "-".repeat(len as usize);
+        write!(self.stdout, "{}{}", cursor::Goto(x, y), border).unwrap();
         write!(
             self.stdout,
-            "{}------------------------",
-            cursor::Goto((w / 2) - 10, h / 2 - 1)
-        )
-        .unwrap();
-        write!(
-            self.stdout,
-            "{}|    Player {} WINS!    |",
-            cursor::Goto((w / 2) - 10, h / 2),
-            winner
-        )
-        .unwrap();
-        write!(
-            self.stdout,
-            "{}------------------------",
-            cursor::Goto((w / 2) - 10, h / 2 + 1)
+            "{}{}{}{}",
+            cursor::Goto(x, y + 1),
+            color::Fg(snake_color(winner.snake)),
+            msg,
+            color::Fg(color::Reset)
         )
         .unwrap();
+        write!(self.stdout, "{}{}", cursor::Goto(x, y + 2), border).unwrap();
         self.stdout.flush().unwrap();
     }
 
     fn draw_scores(&mut self, game: &Game) -> u16 {
         let mut current_y = MARGIN_TOP;
 
-        fn score_msg(status: &PlayerStatus) -> String {
-            match status {
-                PlayerStatus::Leaver => "Left the game".to_owned(),
-                PlayerStatus::Loser => "Lost".to_owned(),
-                PlayerStatus::Player(score) => score.to_string(),
-            }
-        }
-
         for snake in self.snakes_nb.iter() {
             current_y += 1;
             write!(
                 self.stdout,
-                "{}You are {}Snake {}{}",
-                cursor::Goto(client_width() + MARGIN_AFTER_FIELD, current_y),
-                color::Fg(PLAYERS_COLORS[*snake as usize - 1]),
-                snake,
+                "{}You are {}{} {}{}",
+                cursor::Goto(self.client_width() + MARGIN_AFTER_FIELD, current_y),
+                color::Fg(snake_color(*snake)),
+                body_char(*snake),
+                game.name(*snake),
                 color::Fg(color::Reset)
             )
             .unwrap();
         }
+        if self.waiting {
+            current_y += 1;
+            write!(
+                self.stdout,
+                "{}You are waiting for the next round",
+                cursor::Goto(self.client_width() + MARGIN_AFTER_FIELD, current_y)
+            )
+            .unwrap();
+        }
         current_y += 1;
 
         for (i, score) in game.scores.iter().enumerate() {
             current_y += 1;
+            let snake = i as u32 + 1;
+            let connection = match game.latencies.get(i) {
+                // Le joueur a perdu la connexion, son serpent l'attend
+                _ if game.frozen.contains(&snake) => String::from(" (away)"),
+                // Un bot joue à la place, ou a repris le serpent d'un joueur parti
+                _ if game.bots.contains(&snake) => String::from(" (bot)"),
+                Some(Some(latency)) => format!(" {}ms", latency),
+                _ => String::new(),
+            };
             write!(
                 self.stdout,
-                "{}{}Score {}: {}{}",
-                cursor::Goto(client_width() + MARGIN_AFTER_FIELD, current_y),
-                color::Fg(PLAYERS_COLORS[i]),
-                i + 1,
+                "{}{}{} {}: {}{}{}",
+                cursor::Goto(self.client_width() + MARGIN_AFTER_FIELD, current_y),
+                color::Fg(snake_color(snake)),
+                body_char(snake),
+                game.name(snake),
                 score_msg(score),
+                connection,
                 color::Fg(color::Reset)
             )
             .unwrap();
@@ -368,7 +746,6 @@ impl Termion {
 
 impl Termion {
     const BORDER_CHAR: char = '#';
-    const BODY_CHAR: char = '▪';
     const HEAD_UP: char = '▲';
     const HEAD_DOWN: char = '▼';
     const HEAD_LEFT: char = '◀';
@@ -383,3 +760,16 @@ impl Termion {
         }
     }
 }
+
+// Le terminal est aussi rendu quand le client s'arrête sur une erreur
+impl Drop for Termion {
+    fn drop(&mut self) {
+        if self.restored {
+            return;
+        }
+        // Le message d'erreur s'affiche sous la partie
+        let bottom = terminal_size().map_or(self.height + 1, |(_, rows)| rows);
+        let _ = write!(self.stdout, "{}\r\n", cursor::Goto(1, bottom));
+        self.restore();
+    }
+}
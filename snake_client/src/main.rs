@@ -1,87 +1,291 @@
 pub mod client;
-pub mod shared_structures;
+pub mod prediction;
+pub mod udp;
+use clap::Parser;
 use client::{max_players_on_terminal, Drawer, Termion};
-use shared_structures::ServerMsg::*;
-use shared_structures::{ClientMsg, ServerMsg, SERVER_ADDR};
-use std::env;
+use prediction::Prediction;
+use snake_protocol::ServerMsg::*;
+use snake_protocol::{
+    check_name, read_msg, resolve_addr, write_msg, Capability, ClientMsg, Encoding, Handshake,
+    HandshakeAck, ProtocolError, RoomChoice, ServerMsg, Session, DEFAULT_ROOM, DEFAULT_SERVER_ADDR,
+};
 use std::fs::OpenOptions;
 use std::io::prelude::*;
-use std::mem::drop;
 use std::net::TcpStream;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+use udp::UdpChannel;
 
-pub(crate) const LOG_FILE: &'static str = "log";
+pub(crate) const LOG_FILE: &str = "log";
+// Intervalle de lecture du clavier quand le serveur n'envoie rien
+const INPUT_PERIOD: Duration = Duration::from_millis(20);
+// Intervalle entre deux tentatives de reconnexion
+const RECONNECT_PERIOD: Duration = Duration::from_millis(500);
 
-enum ErrorFromServer {
-    MessageError(serde_json::error::Error),
-    StreamError(std::io::Error),
+// Le client a reçu SIGTERM ou SIGHUP, ou SIGINT quand le terminal
+// n'est pas en mode raw
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+// Écran affiché par le client
+enum Phase {
+    Lobby,
+    Playing,
+    End,
+}
+
+#[derive(Parser)]
+#[command(about = "Terminal client of the multiplayer snake game")]
+struct Args {
+    /// Number of players on this terminal
+    #[arg(default_value_t = 1)]
+    nb_players: u32,
+    /// Address of the server, e.g. 127.0.0.1:12345 or [::1]:12345
+    #[arg(long, env = "SNAKE_SERVER", default_value = DEFAULT_SERVER_ADDR)]
+    server: String,
+    /// Name of the room to join
+    #[arg(long, default_value = DEFAULT_ROOM, conflicts_with = "create")]
+    room: String,
+    /// Create a new room with this name instead of joining one
+    #[arg(long)]
+    create: Option<String>,
+    /// Watch the game of the room without playing
+    #[arg(long, conflicts_with_all = ["create", "nb_players"])]
+    spectate: bool,
+    /// Nickname of a player, repeat it for each player on this terminal
+    #[arg(long = "name", conflicts_with = "spectate")]
+    names: Vec<String>,
+    /// Exchange readable JSON messages instead of the binary encoding
+    #[arg(long)]
+    json: bool,
+    /// Receive the game and send directions over UDP, lost packets do not
+    /// delay the next turns
+    #[arg(long)]
+    udp: bool,
 }
 
 fn main() {
-    match get_nb_of_players() {
+    let args = Args::parse();
+    match get_nb_of_players(&args) {
         Ok(nb_players) => {
-            let mut stream: TcpStream = TcpStream::connect(SERVER_ADDR)
-                .expect(&format!("No server found at address: {}\n", SERVER_ADDR));
-
-            // Dis au serveur le nombre de joueurs sur ce client
-            send_msg_to_server(ClientMsg::Init(nb_players), &mut stream);
-
-            let serpents: Vec<u32>;
-            match listen_server(&mut stream) {
-                Ok(ServerMsg::InitAck(serpents_nb)) => serpents = serpents_nb,
-                Ok(ServerMsg::Error(msg)) => error_msg_from_server(None, msg),
-                _ => {
-                    println!("Wrong message from server");
+            let mut stream = match connect(&args.server) {
+                Ok(stream) => stream,
+                Err(msg) => {
+                    println!("ERROR: {}", msg);
                     return;
                 }
+            };
+
+            // Dis au serveur le nombre de joueurs sur ce client et le salon choisi
+            let room = match args.create {
+                Some(name) => RoomChoice::Create(name),
+                None => RoomChoice::Join(args.room),
+            };
+            let mut handshake = match args.spectate {
+                true => Handshake::spectator(room),
+                false => Handshake::new(nb_players, room),
+            };
+            handshake.names = args.names.clone();
+            if args.json {
+                handshake
+                    .capabilities
+                    .retain(|c| *c != Capability::BinaryEncoding);
             }
+            if !args.udp {
+                handshake
+                    .capabilities
+                    .retain(|c| *c != Capability::UdpUpdates);
+            }
+            // Le handshake et sa réponse sont toujours en JSON
+            send_msg_to_server(
+                ClientMsg::Init(handshake.clone()),
+                &mut stream,
+                Encoding::Json,
+            );
 
-            let mut client: Termion = Termion::init(nb_players, serpents);
-            let mut playing;
+            let (mut serpents, mut session, mut encoding, udp_key) =
+                match listen_server(&mut stream, Encoding::Json) {
+                    Ok(ServerMsg::InitAck(ack)) => {
+                        let encoding = Encoding::negotiated(&ack.capabilities);
+                        (ack.snakes, ack.session, encoding, ack.udp_key)
+                    }
+                    Ok(ServerMsg::Error(msg)) => error_msg_from_server(None, msg),
+                    _ => {
+                        println!("Wrong message from server");
+                        return;
+                    }
+                };
+
+            // Un spectateur n'a aucun serpent à contrôler
+            let mut client: Termion = Termion::init(serpents.len() as u32, serpents.clone());
+            // Arrivé pendant une partie sans place libre
+            if serpents.is_empty() && !args.spectate {
+                client.wait_for_seat();
+            }
+            // Le client quitte le salon et rend le terminal avant de s'arrêter
+            if let Err(e) = ctrlc::set_handler(|| INTERRUPTED.store(true, Ordering::SeqCst)) {
+                log_in_file(format!("Could not handle termination signals: {}", e));
+            }
+            // Les messages reçus par TCP et par UDP arrivent dans le même canal
+            let (sender, server) = channel();
+            listen_in_background(&stream, encoding, sender.clone());
+            // Sans UDP l'état de la partie arrive par TCP
+            let mut udp = udp_key.and_then(|key| {
+                UdpChannel::open(&stream, key, encoding, sender.clone())
+                    .map_err(|e| log_in_file(format!("Could not open UDP channel: {}", e)))
+                    .ok()
+            });
+            let mut phase = Phase::Lobby;
+            // Dernier état connu de la partie, mis à jour par les deltas
+            // et complété par les commandes des joueurs
+            let mut game: Option<Prediction> = None;
             loop {
-                // Reçoit les messages du serveur
-                match listen_server(&mut stream) {
-                    Ok(msg) => match msg {
+                if INTERRUPTED.load(Ordering::SeqCst) {
+                    let nb_players = serpents.len() as u32;
+                    send_msg_to_server(ClientMsg::Leave(nb_players), &mut stream, encoding);
+                    client.close("Interrupted, you left the game");
+                    return;
+                }
+                // Reçoit les messages du serveur, sans bloquer la lecture du clavier
+                match server.recv_timeout(INPUT_PERIOD) {
+                    Ok(Ok(msg)) => match msg {
                         ServerMsg::Error(e) => error_msg_from_server(Some(client), e),
-                        Playing(game, _) => {
-                            playing = true;
-                            client.draw_game(&game)
+                        Lobby(lobby) => {
+                            phase = Phase::Lobby;
+                            client.draw_lobby(&lobby)
+                        }
+                        // Un datagramme peut arriver en double ou après un plus récent
+                        Playing(_, _, tick)
+                            if game.as_ref().is_some_and(|game| tick.id <= game.tick()) =>
+                        {
+                            log_in_file(format!("Ignoring state of tick {}", tick.id))
+                        }
+                        Playing(keyframe, _, tick) => {
+                            phase = Phase::Playing;
+                            match &mut game {
+                                Some(game) => game.reset(keyframe, tick),
+                                None => {
+                                    game = Some(Prediction::new(keyframe, tick, serpents.clone()))
+                                }
+                            }
+                        }
+                        // Le serveur envoie toujours la partie entière avant un delta,
+                        // un delta ne s'applique qu'à l'état du tour précédent
+                        Delta(delta, _, tick) => {
+                            match game.as_mut().is_some_and(|game| game.apply(&delta, tick)) {
+                                true => phase = Phase::Playing,
+                                false => log_in_file(format!("Ignoring delta of tick {}", tick.id)),
+                            }
                         }
                         End(winner) => {
                             client.draw_end(winner);
-                            playing = false;
+                            phase = Phase::End;
+                        }
+                        // Le serveur vérifie que le client répond toujours
+                        Ping(ping) => {
+                            send_msg_to_server(ClientMsg::Pong(ping), &mut stream, encoding)
+                        }
+                        Chat(msg) => client.add_chat(msg),
+                        // La place attendue est libre pour la partie qui commence
+                        Seats(snakes, new_session) => {
+                            client.seat(snakes.clone());
+                            if let Some(game) = &mut game {
+                                game.set_snakes(snakes.clone());
+                            }
+                            serpents = snakes;
+                            session = new_session;
+                        }
+                        Shutdown(reason) => {
+                            client.close(&format!("The server is shutting down: {}", reason));
+                            return;
                         }
                         _ => panic!("Should not happen"),
                     },
-                    Err(ErrorFromServer::MessageError(e)) => match e.classify() {
-                        serde_json::error::Category::Eof => error_msg_from_server(
-                            Some(client),
-                            String::from("Server has disconnected"),
-                        ),
-                        _ => error_msg_from_server(
-                            Some(client),
-                            format!("MessageError: {}", e.to_string()),
-                        ),
-                    },
-                    Err(ErrorFromServer::StreamError(e)) => match e.kind() {
-                        std::io::ErrorKind::ConnectionReset => error_msg_from_server(
-                            Some(client),
-                            String::from("Server has disconnected"),
-                        ),
-                        std::io::ErrorKind::UnexpectedEof => error_msg_from_server(
-                            Some(client),
-                            String::from("Server has disconnected"),
-                        ),
-
-                        _ => error_msg_from_server(Some(client), String::from("toto")),
-                    },
+                    // Pendant une partie le serveur garde les serpents du joueur
+                    // quelques secondes, le temps de revenir
+                    Ok(Err(ProtocolError::Stream(_)))
+                        if session.is_some() && !matches!(phase, Phase::Lobby) =>
+                    {
+                        client.draw_connection_lost();
+                        let previous = session.as_ref().unwrap();
+                        match reconnect(&args.server, &handshake, previous) {
+                            Ok((new_stream, ack)) => {
+                                stream = new_stream;
+                                encoding = Encoding::negotiated(&ack.capabilities);
+                                listen_in_background(&stream, encoding, sender.clone());
+                                session = ack.session;
+                                udp = match (udp.take(), ack.udp_key) {
+                                    (Some(mut udp), Some(key)) => {
+                                        udp.set_key(key);
+                                        Some(udp)
+                                    }
+                                    _ => None,
+                                };
+                            }
+                            Err(msg) => error_msg_from_server(Some(client), msg),
+                        }
+                    }
+                    Ok(Err(e)) => protocol_error(client, e),
+                    Err(RecvTimeoutError::Timeout) => (),
+                    Err(RecvTimeoutError::Disconnected) => {
+                        error_msg_from_server(Some(client), String::from("Server has disconnected"))
+                    }
                 }
                 // Récupère les touches des joueurs
-                if playing {
-                    let inputs = client.get_inputs();
-                    if let ClientMsg::Leave(_) = inputs {
-                        return;
+                match phase {
+                    Phase::Lobby => {
+                        for msg in client.get_lobby_inputs() {
+                            let leave = matches!(msg, ClientMsg::Leave(_));
+                            send_msg_to_server(msg, &mut stream, encoding);
+                            if leave {
+                                client.close("You left the room");
+                                return;
+                            }
+                        }
+                    }
+                    // Entre deux parties le joueur peut aussi quitter ou discuter
+                    Phase::Playing | Phase::End => match client.get_inputs() {
+                        // Le serveur sait ainsi que le joueur ne reviendra pas
+                        ClientMsg::Leave(nb_players) => {
+                            send_msg_to_server(ClientMsg::Leave(nb_players), &mut stream, encoding);
+                            client.close("You left the game");
+                            return;
+                        }
+                        // Sans nouvelle commande le serveur garde la direction des serpents
+                        ClientMsg::SnakeDirection(_, directions)
+                            if directions.iter().all(Vec::is_empty) => {}
+                        // Les virages pris pendant la pause ne s'appliquent à aucun tour
+                        ClientMsg::SnakeDirection(_, _) if matches!(phase, Phase::End) => {}
+                        ClientMsg::SnakeDirection(sequence, directions) => {
+                            if let Some(game) = &mut game {
+                                game.input(sequence, directions.clone());
+                            }
+                            // Tant que le serveur n'a pas reçu de datagramme, les
+                            // commandes passent aussi par TCP: l'UDP du client vers
+                            // le serveur est peut-être bloqué. Le serveur ignore
+                            // celles reçues deux fois
+                            if udp.as_ref().is_none_or(|udp| !udp.confirmed()) {
+                                send_msg_to_server(
+                                    ClientMsg::SnakeDirection(sequence, directions),
+                                    &mut stream,
+                                    encoding,
+                                );
+                            }
+                        }
+                        inputs => send_msg_to_server(inputs, &mut stream, encoding),
+                    },
+                }
+                // Les commandes passent par UDP tant que le serveur ne les a pas appliquées
+                if let Some(udp) = &mut udp {
+                    let inputs = game.as_ref().map(Prediction::pending_inputs);
+                    udp.send(&inputs.unwrap_or_default());
+                }
+                // Affiche la partie prédite sans attendre le prochain message du serveur
+                if let (Phase::Playing, Some(game)) = (&phase, &mut game) {
+                    if let Some(frame) = game.frame(Instant::now()) {
+                        client.draw_game(&frame);
                     }
-                    send_msg_to_server(inputs, &mut stream);
                 }
             }
         }
@@ -89,58 +293,118 @@ fn main() {
     }
 }
 
-fn get_nb_of_players() -> Result<u32, String> {
-    let args: Vec<String> = env::args().collect();
+fn get_nb_of_players(args: &Args) -> Result<u32, String> {
+    if args.nb_players > max_players_on_terminal() {
+        return Err(format!("Maximum {} players", max_players_on_terminal()));
+    }
+    // Le serveur vérifie aussi les surnoms, autant prévenir avant de se connecter
+    if args.names.len() > args.nb_players as usize {
+        return Err(format!(
+            "{} nicknames for {} players",
+            args.names.len(),
+            args.nb_players
+        ));
+    }
+    for name in args.names.iter() {
+        check_name(name)?;
+    }
+    Ok(args.nb_players)
+}
 
-    if args.len() > 1 {
-        if let Ok(nb_players) = args[1].parse::<u32>() {
-            if nb_players <= max_players_on_terminal() {
-                return Ok(nb_players);
-            } else {
-                println!("{} {}", nb_players, max_players_on_terminal());
-                return Err(format!("Maximum {} players", max_players_on_terminal()));
-            }
-        } else {
-            return Err(String::from("Arguments must be integers"));
+fn connect(addr: &str) -> Result<TcpStream, String> {
+    let addrs = resolve_addr(addr)?;
+    TcpStream::connect(&addrs[..]).map_err(|e| format!("No server found at {}: {}", addr, e))
+}
+
+// Lit les messages du serveur dans un thread, le dernier message transmis
+// est l'erreur qui a arrêté la lecture
+fn listen_in_background(
+    stream: &TcpStream,
+    encoding: Encoding,
+    sender: Sender<Result<ServerMsg, ProtocolError>>,
+) {
+    let mut reader = stream.try_clone().expect("Could not clone server stream");
+    thread::spawn(move || loop {
+        let msg = listen_server(&mut reader, encoding);
+        let stop = msg.is_err();
+        if sender.send(msg).is_err() || stop {
+            return;
         }
+    });
+}
+
+fn listen_server(stream: &mut TcpStream, encoding: Encoding) -> Result<ServerMsg, ProtocolError> {
+    let msg = read_msg(stream, encoding)?;
+    log_in_file(format!("{:?}", msg));
+    Ok(msg)
+}
+
+// Une erreur d'envoi est aussi vue par le thread qui lit les messages du serveur
+fn send_msg_to_server(msg: ClientMsg, stream: &mut TcpStream, encoding: Encoding) {
+    if let Err(e) = write_msg(stream, &msg, encoding) {
+        log_in_file(format!("Could not send {:?}: {}", msg, e));
     }
-    Ok(1)
 }
 
-fn listen_server(stream: &mut TcpStream) -> Result<ServerMsg, ErrorFromServer> {
-    let mut buffer = [0; 1024];
-    match stream.read(&mut buffer) {
-        Ok(_) => {
-            let json = String::from_utf8_lossy(&buffer);
-            let json = &json.trim_end_matches(char::from(0));
-            log_in_file(String::from(*json));
-            match serde_json::from_str(&json) {
-                Ok(msg) => Ok(msg),
-                Err(e) => Err(ErrorFromServer::MessageError(e)),
+// Reprend la place du client dans son salon après une coupure de connexion,
+// tant que le délai accordé par le serveur n'est pas écoulé
+fn reconnect(
+    addr: &str,
+    handshake: &Handshake,
+    session: &Session,
+) -> Result<(TcpStream, HandshakeAck), String> {
+    let deadline = Instant::now() + Duration::from_secs(session.grace);
+    let mut handshake = handshake.clone();
+    handshake.session = Some(session.token.clone());
+    // Le salon a pu être créé par ce client, il existe maintenant
+    handshake.room = RoomChoice::Join(handshake.room.name().to_string());
+    loop {
+        if let Ok(mut stream) = connect(addr) {
+            send_msg_to_server(
+                ClientMsg::Init(handshake.clone()),
+                &mut stream,
+                Encoding::Json,
+            );
+            match listen_server(&mut stream, Encoding::Json) {
+                Ok(ServerMsg::InitAck(ack)) => return Ok((stream, ack)),
+                Ok(ServerMsg::Error(msg)) => return Err(msg),
+                _ => (),
             }
         }
-        // TODO handle properly
-        Err(e) => Err(ErrorFromServer::StreamError(e)),
+        if Instant::now() >= deadline {
+            return Err(String::from("Could not reconnect to the server"));
+        }
+        thread::sleep(RECONNECT_PERIOD);
     }
 }
 
-fn send_msg_to_server(msg: ClientMsg, stream: &mut TcpStream) {
-    let json = serde_json::to_string(&msg).unwrap();
-    stream.write(json.as_bytes()).unwrap();
-    stream.flush().unwrap()
-}
-
+// process::exit n'appelle pas les destructeurs, le terminal est rendu avant
 fn error_msg_from_server(client: Option<Termion>, error_msg: String) -> ! {
-    if let Some(client) = client {
-        drop(client);
+    let error_msg = format!("[SERVER ERROR] {}", error_msg);
+    match client {
+        Some(client) => client.close(&error_msg),
+        None => println!("{}", error_msg),
     }
-    println!("[SERVER ERROR] {}", error_msg);
     std::process::exit(1)
 }
 
+fn protocol_error(client: Termion, e: ProtocolError) -> ! {
+    match e {
+        ProtocolError::Message(_) | ProtocolError::Binary(_) => {
+            error_msg_from_server(Some(client), e.to_string())
+        }
+        ProtocolError::Stream(e) => match e.kind() {
+            std::io::ErrorKind::ConnectionReset | std::io::ErrorKind::UnexpectedEof => {
+                error_msg_from_server(Some(client), String::from("Server has disconnected"))
+            }
+            _ => error_msg_from_server(Some(client), format!("StreamError: {}", e)),
+        },
+    }
+}
+
 fn log_in_file(mut s: String) {
     s.push('\n');
     if let Ok(mut file) = OpenOptions::new().append(true).open(LOG_FILE) {
-        file.write(s.as_bytes()).unwrap();
+        file.write_all(s.as_bytes()).unwrap();
     }
 }
@@ -0,0 +1,353 @@
+use snake_protocol::snake::DirectionQueue;
+use snake_protocol::{ClientMsg, Direction, Game, GameDelta, Point, Snake, Tick};
+use std::time::Instant;
+
+// Nombre maximal de tours joués par le client en avance sur le serveur
+const MAX_STEPS_AHEAD: u32 = 3;
+// Au-delà de cet écart entre la tête affichée et la tête prédite, le
+// serpent est replacé d'un coup plutôt que ramené case par case
+const MAX_CORRECTION: u32 = 2 * MAX_STEPS_AHEAD + 1;
+
+// État de la partie vu par le client: le dernier état reçu du serveur,
+// corrigé par les commandes des joueurs que le serveur n'a pas encore appliquées
+pub struct Prediction {
+    // Dernier état confirmé par le serveur
+    game: Game,
+    tick: u64,
+    received: Instant,
+    // Serpents contrôlés par les joueurs de ce client
+    snakes: Vec<u32>,
+    // Commandes envoyées mais pas encore prises en compte par le serveur
+    pending: Vec<(u64, Vec<Vec<Direction>>)>,
+    // Tours d'avance de l'état affiché, None s'il doit être redessiné
+    drawn: Option<u32>,
+    // Serpents du client tels qu'affichés, ramenés vers la prédiction d'une
+    // case par image quand elle change
+    shown: Vec<Option<Snake>>,
+}
+
+impl Prediction {
+    pub fn new(game: Game, tick: Tick, snakes: Vec<u32>) -> Self {
+        let mut prediction = Prediction {
+            game,
+            tick: tick.id,
+            received: Instant::now(),
+            snakes,
+            pending: vec![],
+            drawn: None,
+            shown: vec![],
+        };
+        prediction.acknowledge(tick);
+        prediction
+    }
+
+    // Remplace l'état confirmé par une partie entière reçue du serveur
+    pub fn reset(&mut self, game: Game, tick: Tick) {
+        self.game = game;
+        self.tick = tick.id;
+        self.acknowledge(tick);
+    }
+
+    // Applique un delta, retourne false s'il ne suit pas l'état confirmé
+    pub fn apply(&mut self, delta: &GameDelta, tick: Tick) -> bool {
+        if tick.id != self.tick + 1 {
+            return false;
+        }
+        self.game.apply(delta);
+        self.tick = tick.id;
+        self.acknowledge(tick);
+        true
+    }
+
+    // Serpents donnés au client après son arrivée
+    pub fn set_snakes(&mut self, snakes: Vec<u32>) {
+        self.snakes = snakes;
+        self.drawn = None;
+        self.shown.clear();
+    }
+
+    // Numéro du tour de l'état confirmé
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    // Commandes que le serveur n'a pas encore appliquées
+    pub fn pending_inputs(&self) -> Vec<ClientMsg> {
+        self.pending
+            .iter()
+            .map(|(sequence, directions)| ClientMsg::SnakeDirection(*sequence, directions.clone()))
+            .collect()
+    }
+
+    // Commande envoyée au serveur, jouée tout de suite sur l'état affiché
+    pub fn input(&mut self, sequence: u64, directions: Vec<Vec<Direction>>) {
+        self.pending.push((sequence, directions));
+        self.drawn = None;
+    }
+
+    // État à afficher, None s'il n'a pas changé depuis le dernier appel.
+    // Les serpents du client rejoignent la prédiction en quelques images
+    pub fn frame(&mut self, now: Instant) -> Option<Game> {
+        let steps = self.steps_ahead(now);
+        if self.drawn == Some(steps) {
+            return None;
+        }
+        let mut game = self.predict(steps);
+        self.shown.resize(self.snakes.len(), None);
+        let mut converged = true;
+        for (id, shown) in self.snakes.iter().zip(self.shown.iter_mut()) {
+            let target = match game.snakes.get_mut(*id as usize - 1) {
+                Some(target) => target,
+                None => continue,
+            };
+            converged &= blend(shown, target.as_ref());
+            target.clone_from(shown);
+        }
+        // Tant que l'affichage n'a pas rejoint la prédiction, l'image suivante
+        // doit être redessinée
+        self.drawn = converged.then_some(steps);
+        Some(game)
+    }
+
+    // Les commandes appliquées par le serveur sont déjà dans son état
+    fn acknowledge(&mut self, tick: Tick) {
+        self.pending
+            .retain(|(sequence, _)| *sequence > tick.last_input);
+        self.received = Instant::now();
+        self.drawn = None;
+    }
+
+    // Une commande envoyée maintenant est appliquée par le serveur, et son
+    // effet reçu, au bout d'un aller-retour: les serpents du client sont
+    // affichés autant de tours en avance sur l'état confirmé, plus les tours
+    // dont l'état n'est pas encore arrivé
+    fn steps_ahead(&self, now: Instant) -> u32 {
+        let speed = self.game.speed.max(1) as u128;
+        let rtt = match self.rtt() {
+            Some(rtt) => rtt as u128,
+            None => return 0,
+        };
+        let lead = (rtt + speed / 2) / speed;
+        let elapsed = now.saturating_duration_since(self.received).as_millis();
+        let late = elapsed / speed;
+        (lead + late).min(MAX_STEPS_AHEAD as u128) as u32
+    }
+
+    // Aller-retour mesuré par le serveur avec ses pings, en millisecondes
+    fn rtt(&self) -> Option<u32> {
+        self.snakes
+            .iter()
+            .find_map(|id| self.game.latencies.get(*id as usize - 1).copied().flatten())
+    }
+
+    // Rejoue les commandes en attente sur l'état confirmé et fait avancer
+    // les serpents du client, les autres serpents restent à leur place.
+    // Comme sur le serveur, un virage est pris au début de chaque tour.
+    // Un serpent n'est jamais prédit sur une bordure: le serveur le fera perdre
+    fn predict(&self, steps: u32) -> Game {
+        let mut game = self.game.clone();
+        let (width, height) = (game.width, game.height);
+        for (i, id) in self.snakes.iter().enumerate() {
+            let food = game.food.clone();
+            let frozen = game.frozen.contains(id);
+            let snake = match game.snakes.get_mut(*id as usize - 1) {
+                Some(Some(snake)) => snake,
+                _ => continue,
+            };
+            let mut queue = DirectionQueue::default();
+            for (_, directions) in self.pending.iter() {
+                for d in directions.get(i).into_iter().flatten() {
+                    queue.push(&snake.direction, d.clone());
+                }
+            }
+            if let Some(d) = queue.pop() {
+                snake.change_direction(d);
+            }
+            for _ in 0..steps {
+                if frozen || !inside(&snake.head, width, height) {
+                    break;
+                }
+                let next = Point::next_point(&snake.head, &snake.direction);
+                if !inside(&next, width, height) {
+                    break;
+                }
+                snake.step(next == food);
+                if let Some(d) = queue.pop() {
+                    snake.change_direction(d);
+                }
+            }
+        }
+        game
+    }
+}
+
+// Avance le serpent affiché d'une case vers le serpent prédit, ou le remplace
+// quand il est trop loin. Retourne true quand les deux sont identiques
+fn blend(shown: &mut Option<Snake>, target: Option<&Snake>) -> bool {
+    let (snake, target) = match (shown.as_mut(), target) {
+        (Some(snake), Some(target)) if snake == target => return true,
+        (Some(snake), Some(target)) if distance(&snake.head, &target.head) <= MAX_CORRECTION => {
+            (snake, target)
+        }
+        _ => {
+            *shown = target.cloned();
+            return true;
+        }
+    };
+    if snake.head != target.head {
+        let (dx, dy) = Point::sub(&target.head, &snake.head);
+        snake.direction = if dx.abs() >= dy.abs() {
+            if dx > 0 {
+                Direction::Right
+            } else {
+                Direction::Left
+            }
+        } else if dy > 0 {
+            Direction::Down
+        } else {
+            Direction::Up
+        };
+        snake.step(snake.body.len() < target.body.len());
+        while snake.body.len() > target.body.len() {
+            snake.body.remove(0);
+        }
+    }
+    // La tête est en place, le reste du corps suit la prédiction
+    if snake.head == target.head {
+        snake.clone_from(target);
+        return true;
+    }
+    false
+}
+
+// Le point est-il dans le terrain, bordures exclues
+fn inside(p: &Point, width: u16, height: u16) -> bool {
+    p.x > 1 && p.y > 1 && p.x < width && p.y < height
+}
+
+fn distance(a: &Point, b: &Point) -> u32 {
+    let (dx, dy) = Point::sub(a, b);
+    dx.unsigned_abs() as u32 + dy.unsigned_abs() as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game(latency: Option<u32>) -> Game {
+        let mut game = Game::new(2, 1, 40, 21, Point::new(1, 1));
+        game.latencies[0] = latency;
+        game.speed = 150;
+        game
+    }
+
+    fn ahead(snake: &Snake, steps: u32) -> Snake {
+        let mut snake = snake.clone();
+        for _ in 0..steps {
+            snake.step(false);
+        }
+        snake
+    }
+
+    fn head_of(game: &Game, id: u32) -> Point {
+        game.snakes[id as usize - 1].as_ref().unwrap().head.clone()
+    }
+
+    #[test]
+    fn lead_follows_round_trip() {
+        let tick = Tick {
+            id: 1,
+            last_input: 0,
+        };
+        let server = game(Some(300));
+        let expected = ahead(server.snakes[0].as_ref().unwrap(), 2);
+        let mut prediction = Prediction::new(server.clone(), tick, vec![1]);
+        let frame = prediction.frame(Instant::now()).unwrap();
+        assert_eq!(head_of(&frame, 1), expected.head);
+        // Les autres serpents restent à leur place confirmée
+        assert_eq!(head_of(&frame, 2), head_of(&server, 2));
+        assert!(prediction.frame(Instant::now()).is_none());
+
+        let mut prediction = Prediction::new(game(None), tick, vec![1]);
+        let frame = prediction.frame(Instant::now()).unwrap();
+        assert_eq!(head_of(&frame, 1), head_of(&server, 1));
+    }
+
+    // Place le serpent 1 la tête en _head_, le corps derrière lui
+    fn heading(game: &mut Game, head: Point, direction: Direction) {
+        let (dx, dy) = match direction {
+            Direction::Up => (0, 1),
+            Direction::Down => (0, -1),
+            Direction::Left => (1, 0),
+            Direction::Right => (-1, 0),
+        };
+        let body = (1..=3)
+            .rev()
+            .map(|i| {
+                Point::new(
+                    (head.x as i16 + i * dx) as u16,
+                    (head.y as i16 + i * dy) as u16,
+                )
+            })
+            .collect();
+        game.snakes[0] = Some(Snake {
+            id: 1,
+            head,
+            body,
+            direction,
+        });
+    }
+
+    fn predicted_head(server: Game) -> Point {
+        let tick = Tick {
+            id: 1,
+            last_input: 0,
+        };
+        let mut prediction = Prediction::new(server, tick, vec![1]);
+        let frame = prediction.frame(Instant::now()).unwrap();
+        head_of(&frame, 1)
+    }
+
+    #[test]
+    fn prediction_stops_before_left_wall() {
+        let mut server = game(Some(400));
+        heading(&mut server, Point::new(3, 5), Direction::Left);
+        assert_eq!(predicted_head(server.clone()), Point::new(2, 5));
+        heading(&mut server, Point::new(2, 5), Direction::Left);
+        assert_eq!(predicted_head(server), Point::new(2, 5));
+    }
+
+    #[test]
+    fn prediction_stops_before_top_wall() {
+        let mut server = game(Some(400));
+        heading(&mut server, Point::new(10, 3), Direction::Up);
+        assert_eq!(predicted_head(server.clone()), Point::new(10, 2));
+        heading(&mut server, Point::new(10, 2), Direction::Up);
+        assert_eq!(predicted_head(server), Point::new(10, 2));
+    }
+
+    #[test]
+    fn misprediction_corrected_over_frames() {
+        let snake = game(None).snakes[0].clone().unwrap();
+        let target = ahead(&snake, 3);
+        let mut shown = Some(snake);
+        let mut frames = 1;
+        while !blend(&mut shown, Some(&target)) {
+            frames += 1;
+            assert!(frames <= 3);
+        }
+        assert_eq!(frames, 3);
+        assert_eq!(shown.as_ref(), Some(&target));
+    }
+
+    #[test]
+    fn far_prediction_replaced() {
+        let snake = game(None).snakes[0].clone().unwrap();
+        let target = game(None).snakes[1].clone().unwrap();
+        let mut shown = Some(snake);
+        assert!(blend(&mut shown, Some(&target)));
+        assert_eq!(shown.as_ref(), Some(&target));
+        assert!(blend(&mut shown, None));
+        assert!(shown.is_none());
+    }
+}
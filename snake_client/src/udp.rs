@@ -0,0 +1,103 @@
+use crate::log_in_file;
+use snake_protocol::{
+    client_datagram, ClientMsg, Encoding, ProtocolError, ServerMsg, MAX_DATAGRAM_SIZE,
+};
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Intervalle entre deux datagrammes quand le client n'a pas de commande
+// à envoyer, le serveur connaît ainsi l'adresse du client
+const KEEPALIVE_PERIOD: Duration = Duration::from_secs(1);
+
+// Canal UDP vers le serveur pour l'état de la partie et les directions
+pub struct UdpChannel {
+    socket: UdpSocket,
+    key: u64,
+    encoding: Encoding,
+    sent_at: Option<Instant>,
+    // Le serveur n'envoie l'état de la partie par UDP qu'après avoir reçu un
+    // datagramme du client: en recevoir un prouve que les deux sens passent
+    confirmed: Arc<AtomicBool>,
+}
+
+impl UdpChannel {
+    // Ouvre un socket vers le serveur de la connexion TCP. Les messages reçus
+    // sont transmis avec ceux de la connexion TCP, les datagrammes perdus ou
+    // invalides sont ignorés
+    pub fn open(
+        stream: &TcpStream,
+        key: u64,
+        encoding: Encoding,
+        sender: Sender<Result<ServerMsg, ProtocolError>>,
+    ) -> io::Result<Self> {
+        let server = stream.peer_addr()?;
+        let local = match server {
+            SocketAddr::V4(_) => SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)),
+            SocketAddr::V6(_) => SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0)),
+        };
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(server)?;
+        let reader = socket.try_clone()?;
+        let confirmed = Arc::new(AtomicBool::new(false));
+        let received = confirmed.clone();
+        thread::spawn(move || {
+            let mut buffer = vec![0; MAX_DATAGRAM_SIZE];
+            loop {
+                match reader.recv(&mut buffer) {
+                    Ok(len) => match encoding.decode(&buffer[..len]) {
+                        Ok(msg) => {
+                            received.store(true, Ordering::Relaxed);
+                            if sender.send(Ok(msg)).is_err() {
+                                return;
+                            }
+                        }
+                        Err(e) => log_in_file(format!("Invalid datagram: {}", e)),
+                    },
+                    Err(e) => log_in_file(format!("Could not receive a datagram: {}", e)),
+                }
+            }
+        });
+        Ok(UdpChannel {
+            socket,
+            key,
+            encoding,
+            sent_at: None,
+            confirmed,
+        })
+    }
+
+    // Nouvelle clé donnée par le serveur après une reconnexion
+    pub fn set_key(&mut self, key: u64) {
+        self.key = key;
+        self.sent_at = None;
+        self.confirmed.store(false, Ordering::Relaxed);
+    }
+
+    // Le serveur reçoit les datagrammes du client
+    pub fn confirmed(&self) -> bool {
+        self.confirmed.load(Ordering::Relaxed)
+    }
+
+    // Envoie les commandes que le serveur n'a pas encore appliquées, le
+    // prochain datagramme les répète si celui-ci se perd
+    pub fn send(&mut self, inputs: &[ClientMsg]) {
+        if inputs.is_empty()
+            && self
+                .sent_at
+                .is_some_and(|sent_at| sent_at.elapsed() < KEEPALIVE_PERIOD)
+        {
+            return;
+        }
+        let sent = client_datagram(self.key, inputs, self.encoding)
+            .and_then(|datagram| self.socket.send(&datagram));
+        match sent {
+            Ok(_) => self.sent_at = Some(Instant::now()),
+            Err(e) => log_in_file(format!("Could not send a datagram: {}", e)),
+        }
+    }
+}
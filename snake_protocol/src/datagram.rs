@@ -0,0 +1,26 @@
+use crate::{ClientMsg, Encoding};
+use std::convert::TryInto;
+use std::io;
+
+// Taille maximale d'un datagramme. Un datagramme plus grand serait fragmenté
+// et plus souvent perdu, les messages plus grands passent par TCP
+pub const MAX_DATAGRAM_SIZE: usize = 8 * 1024;
+// Taille de la clé qui commence les datagrammes des clients
+const KEY_SIZE: usize = 8;
+
+// Datagramme d'un client: la clé reçue lors du handshake, suivie des
+// commandes que le serveur n'a pas encore appliquées
+pub fn client_datagram(key: u64, inputs: &[ClientMsg], encoding: Encoding) -> io::Result<Vec<u8>> {
+    let mut datagram = key.to_be_bytes().to_vec();
+    datagram.extend(encoding.encode(&inputs)?);
+    Ok(datagram)
+}
+
+// Sépare la clé du client de ses commandes encodées
+pub fn split_client_datagram(datagram: &[u8]) -> Option<(u64, &[u8])> {
+    if datagram.len() < KEY_SIZE {
+        return None;
+    }
+    let (key, inputs) = datagram.split_at(KEY_SIZE);
+    Some((u64::from_be_bytes(key.try_into().ok()?), inputs))
+}
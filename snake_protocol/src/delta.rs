@@ -0,0 +1,247 @@
+use crate::{Direction, Game, PlayerStatus, Point, Snake};
+use serde::{Deserialize, Serialize};
+
+// Déplacement d'un serpent pendant un tour
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SnakeMove {
+    pub id: u32,
+    pub head: Point,
+    pub direction: Direction,
+    // Le serpent a mangé, sa queue n'a pas avancé
+    pub grew: bool,
+}
+
+// Changements de la partie depuis le message précédent
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GameDelta {
+    pub moves: Vec<SnakeMove>,
+    // Serpents qui ont perdu ou quitté la partie
+    pub removed: Vec<u32>,
+    // Scores modifiés, avec le numéro du serpent
+    pub scores: Vec<(u32, PlayerStatus)>,
+    pub food: Option<Point>,
+    pub bots: Option<Vec<u32>>,
+    pub frozen: Option<Vec<u32>>,
+    pub latencies: Option<Vec<Option<u32>>>,
+}
+
+impl SnakeMove {
+    fn apply(&self, snake: &mut Snake) {
+        snake.body.push(snake.head.clone());
+        if !self.grew {
+            snake.body.remove(0);
+        }
+        snake.head = self.head.clone();
+        snake.direction = self.direction.clone();
+    }
+}
+
+impl GameDelta {
+    // Changements pour passer de _old_ à _new_. Retourne None quand ils ne
+    // peuvent pas être décrits par un delta, la partie entière doit être envoyée
+    pub fn diff(old: &Game, new: &Game) -> Option<GameDelta> {
+        // Ces paramètres ne changent pas pendant une partie
+        if old.nb_snakes != new.nb_snakes
+            || old.bots_difficulty != new.bots_difficulty
+            || old.points_to_win != new.points_to_win
+            || old.food_points != new.food_points
+            || old.speed != new.speed
+            || old.width != new.width
+            || old.height != new.height
+            || old.names != new.names
+            || old.snakes.len() != new.snakes.len()
+            || old.scores.len() != new.scores.len()
+        {
+            return None;
+        }
+
+        let mut moves = vec![];
+        let mut removed = vec![];
+        for (old_snake, new_snake) in old.snakes.iter().zip(new.snakes.iter()) {
+            match (old_snake, new_snake) {
+                (Some(o), Some(n)) if o == n => (),
+                (Some(o), Some(n)) => {
+                    let snake_move = SnakeMove {
+                        id: n.id,
+                        head: n.head.clone(),
+                        direction: n.direction.clone(),
+                        grew: n.body.len() > o.body.len(),
+                    };
+                    // Le serpent doit avoir avancé d'une seule case
+                    let mut moved = o.clone();
+                    snake_move.apply(&mut moved);
+                    if moved != *n {
+                        return None;
+                    }
+                    moves.push(snake_move);
+                }
+                (Some(o), None) => removed.push(o.id),
+                (None, None) => (),
+                // Un serpent ne réapparaît pas pendant une partie
+                (None, Some(_)) => return None,
+            }
+        }
+
+        let scores = old
+            .scores
+            .iter()
+            .zip(new.scores.iter())
+            .enumerate()
+            .filter(|(_, (o, n))| o != n)
+            .map(|(i, (_, n))| (i as u32 + 1, n.clone()))
+            .collect();
+
+        Some(GameDelta {
+            moves,
+            removed,
+            scores,
+            food: (old.food != new.food).then(|| new.food.clone()),
+            bots: (old.bots != new.bots).then(|| new.bots.clone()),
+            frozen: (old.frozen != new.frozen).then(|| new.frozen.clone()),
+            latencies: (old.latencies != new.latencies).then(|| new.latencies.clone()),
+        })
+    }
+}
+
+impl Game {
+    // Applique les changements d'un tour reçus du serveur
+    pub fn apply(&mut self, delta: &GameDelta) {
+        for snake_move in delta.moves.iter() {
+            if let Some(Some(snake)) =
+                snake_index(snake_move.id).and_then(|i| self.snakes.get_mut(i))
+            {
+                snake_move.apply(snake);
+            }
+        }
+        for id in delta.removed.iter() {
+            if let Some(snake) = snake_index(*id).and_then(|i| self.snakes.get_mut(i)) {
+                *snake = None;
+            }
+        }
+        for (id, status) in delta.scores.iter() {
+            if let Some(score) = snake_index(*id).and_then(|i| self.scores.get_mut(i)) {
+                *score = status.clone();
+            }
+        }
+        if let Some(food) = &delta.food {
+            self.food = food.clone();
+        }
+        if let Some(bots) = &delta.bots {
+            self.bots = bots.clone();
+        }
+        if let Some(frozen) = &delta.frozen {
+            self.frozen = frozen.clone();
+        }
+        if let Some(latencies) = &delta.latencies {
+            self.latencies = latencies.clone();
+        }
+    }
+}
+
+// Les serpents sont numérotés à partir de 1
+fn snake_index(id: u32) -> Option<usize> {
+    (id as usize).checked_sub(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game() -> Game {
+        let mut game = Game::new(2, 1, 40, 21, Point::new(5, 5));
+        game.latencies[0] = Some(20);
+        game.names[0] = String::from("alice");
+        game
+    }
+
+    fn snake(game: &mut Game, id: u32) -> &mut Snake {
+        game.snakes[id as usize - 1].as_mut().unwrap()
+    }
+
+    // Le client qui applique le delta à l'état précédent retrouve l'état envoyé
+    fn assert_round_trip(old: &Game, new: &Game) -> GameDelta {
+        let delta = GameDelta::diff(old, new).expect("a delta should describe this turn");
+        let mut applied = old.clone();
+        applied.apply(&delta);
+        assert_eq!(
+            serde_json::to_value(&applied).unwrap(),
+            serde_json::to_value(new).unwrap()
+        );
+        delta
+    }
+
+    #[test]
+    fn unchanged_game() {
+        let delta = assert_round_trip(&game(), &game());
+        assert!(delta.moves.is_empty() && delta.removed.is_empty() && delta.scores.is_empty());
+        assert!(delta.food.is_none());
+    }
+
+    #[test]
+    fn snakes_move() {
+        let old = game();
+        let mut new = old.clone();
+        snake(&mut new, 1).step(false);
+        snake(&mut new, 2).change_direction(Direction::Up);
+        snake(&mut new, 2).step(false);
+        let delta = assert_round_trip(&old, &new);
+        assert_eq!(delta.moves.len(), 2);
+        assert!(delta.moves.iter().all(|m| !m.grew));
+    }
+
+    #[test]
+    fn snake_grows() {
+        let old = game();
+        let mut new = old.clone();
+        snake(&mut new, 1).step(true);
+        let delta = assert_round_trip(&old, &new);
+        assert!(delta.moves[0].grew);
+    }
+
+    #[test]
+    fn snake_removed() {
+        let old = game();
+        let mut new = old.clone();
+        new.snakes[1] = None;
+        new.scores[1] = PlayerStatus::Loser;
+        let delta = assert_round_trip(&old, &new);
+        assert_eq!(delta.removed, vec![2]);
+    }
+
+    #[test]
+    fn score_and_food_change() {
+        let old = game();
+        let mut new = old.clone();
+        snake(&mut new, 1).step(true);
+        new.scores[0] = PlayerStatus::Player(10);
+        new.food = Point::new(12, 7);
+        new.latencies = vec![Some(35), None];
+        let delta = assert_round_trip(&old, &new);
+        assert_eq!(delta.scores, vec![(1, PlayerStatus::Player(10))]);
+        assert_eq!(delta.food, Some(Point::new(12, 7)));
+    }
+
+    #[test]
+    fn no_delta_when_snake_reappears() {
+        let mut old = game();
+        old.snakes[1] = None;
+        assert!(GameDelta::diff(&old, &game()).is_none());
+    }
+
+    #[test]
+    fn no_delta_when_names_change() {
+        let old = game();
+        let mut new = old.clone();
+        new.names[1] = String::from("bob");
+        assert!(GameDelta::diff(&old, &new).is_none());
+    }
+
+    #[test]
+    fn no_delta_when_snake_jumps() {
+        let old = game();
+        let mut new = old.clone();
+        snake(&mut new, 1).step(false);
+        snake(&mut new, 1).step(false);
+        assert!(GameDelta::diff(&old, &new).is_none());
+    }
+}
@@ -0,0 +1,201 @@
+use crate::{Capability, MAX_FRAME_SIZE};
+use bincode::Options;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+// Format des messages dans les frames. Le handshake est toujours en JSON,
+// les messages suivants utilisent le format négocié
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    // Lisible, pratique pour déboguer
+    Json,
+    // Compact et rapide à lire, pour les grands terrains et les nombreux serpents
+    Bincode,
+}
+
+#[derive(Debug)]
+pub enum ProtocolError {
+    // The stream was closed or failed
+    Stream(io::Error),
+    // A frame was received but it is not a valid message
+    Message(serde_json::Error),
+    // Same as Message, for the binary encoding
+    Binary(bincode::Error),
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProtocolError::Stream(e) => write!(f, "StreamError: {}", e),
+            ProtocolError::Message(e) => write!(f, "MessageError: {}", e),
+            ProtocolError::Binary(e) => write!(f, "MessageError: {}", e),
+        }
+    }
+}
+
+impl From<io::Error> for ProtocolError {
+    fn from(e: io::Error) -> Self {
+        ProtocolError::Stream(e)
+    }
+}
+
+impl From<serde_json::Error> for ProtocolError {
+    fn from(e: serde_json::Error) -> Self {
+        ProtocolError::Message(e)
+    }
+}
+
+impl From<bincode::Error> for ProtocolError {
+    fn from(e: bincode::Error) -> Self {
+        ProtocolError::Binary(e)
+    }
+}
+
+impl Encoding {
+    // Format choisi selon les fonctionnalités négociées lors du handshake
+    pub fn negotiated(capabilities: &[Capability]) -> Self {
+        match capabilities.contains(&Capability::BinaryEncoding) {
+            true => Encoding::Bincode,
+            false => Encoding::Json,
+        }
+    }
+
+    pub fn encode<T: Serialize>(&self, msg: &T) -> io::Result<Vec<u8>> {
+        match self {
+            Encoding::Json => Ok(serde_json::to_vec(msg)?),
+            Encoding::Bincode => bincode_options()
+                .serialize(msg)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e)),
+        }
+    }
+
+    pub fn decode<T: DeserializeOwned>(&self, frame: &[u8]) -> Result<T, ProtocolError> {
+        match self {
+            Encoding::Json => Ok(serde_json::from_slice(frame)?),
+            Encoding::Bincode => Ok(bincode_options().deserialize(frame)?),
+        }
+    }
+}
+
+// Une longueur mal formée ne doit pas provoquer une allocation plus
+// grande qu'une frame
+fn bincode_options() -> impl Options {
+    bincode::DefaultOptions::new().with_limit(MAX_FRAME_SIZE as u64)
+}
+
+// Serializes a message and sends it as one frame
+pub fn write_msg<W: Write, T: Serialize>(
+    writer: &mut W,
+    msg: &T,
+    encoding: Encoding,
+) -> io::Result<()> {
+    write_frame(writer, &encoding.encode(msg)?)
+}
+
+// Blocks until a whole message has been received
+pub fn read_msg<R: Read, T: DeserializeOwned>(
+    reader: &mut R,
+    encoding: Encoding,
+) -> Result<T, ProtocolError> {
+    let frame = read_frame(reader)?;
+    encoding.decode(&frame)
+}
+
+// Every message on the TCP stream is a frame: its length as a 4 bytes
+// big-endian integer followed by the serialized message
+pub fn write_frame<W: Write>(writer: &mut W, payload: &[u8]) -> io::Result<()> {
+    if payload.len() > MAX_FRAME_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Frame of {} bytes exceeds {} bytes",
+                payload.len(),
+                MAX_FRAME_SIZE
+            ),
+        ));
+    }
+    writer.write_all(&(payload.len() as u32).to_be_bytes())?;
+    writer.write_all(payload)?;
+    writer.flush()
+}
+
+// Blocks until a whole frame has been received, however the stream split it
+pub fn read_frame<R: Read>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let mut header = [0; 4];
+    reader.read_exact(&mut header)?;
+    let len = u32::from_be_bytes(header) as usize;
+    if len > MAX_FRAME_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Frame of {} bytes exceeds {} bytes", len, MAX_FRAME_SIZE),
+        ));
+    }
+    let mut payload = vec![0; len];
+    reader.read_exact(&mut payload)?;
+    Ok(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    // Rend les octets un par un, comme un flux TCP très fragmenté
+    struct ByteByByte(Cursor<Vec<u8>>);
+
+    impl Read for ByteByByte {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = buf.len().min(1);
+            self.0.read(&mut buf[..len])
+        }
+    }
+
+    fn frame(payload: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![];
+        write_frame(&mut bytes, payload).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn frame_split_across_reads() {
+        let mut reader = ByteByByte(Cursor::new(frame(b"{\"Ping\":42}")));
+        assert_eq!(read_frame(&mut reader).unwrap(), b"{\"Ping\":42}");
+    }
+
+    #[test]
+    fn two_frames_in_one_buffer() {
+        let mut bytes = frame(b"first");
+        bytes.extend(frame(b"second"));
+        let mut reader = Cursor::new(bytes);
+        assert_eq!(read_frame(&mut reader).unwrap(), b"first");
+        assert_eq!(read_frame(&mut reader).unwrap(), b"second");
+        assert_eq!(
+            read_frame(&mut reader).unwrap_err().kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+    }
+
+    #[test]
+    fn oversized_header_rejected() {
+        // Seul l'en-tête est envoyé: la frame est refusée sans attendre
+        // ni allouer le contenu annoncé
+        let mut reader = Cursor::new(u32::MAX.to_be_bytes().to_vec());
+        let e = read_frame(&mut reader).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(reader.position(), 4);
+
+        let len = (MAX_FRAME_SIZE as u32 + 1).to_be_bytes();
+        let e = read_frame(&mut Cursor::new(len.to_vec())).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn oversized_payload_not_written() {
+        let mut bytes = vec![];
+        let e = write_frame(&mut bytes, &vec![0; MAX_FRAME_SIZE + 1]).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+        assert!(bytes.is_empty());
+    }
+}
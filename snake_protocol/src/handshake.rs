@@ -0,0 +1,250 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+// Version du protocole, à incrémenter à chaque changement incompatible
+// des messages échangés
+pub const PROTOCOL_VERSION: u32 = 17;
+
+// Fonctionnalités optionnelles du protocole, négociées lors de la connexion.
+// Une fonctionnalité n'est utilisée que si le client et le serveur la supportent
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Capability {
+    // L'état de la partie est envoyé par différences entre deux tours
+    DeltaUpdates,
+    // Les messages qui suivent le handshake sont encodés avec bincode
+    BinaryEncoding,
+    // L'état de la partie et les directions passent par UDP, sur le même
+    // port que la connexion TCP
+    UdpUpdates,
+    // Capability announced by a newer peer, ignored by this version
+    #[serde(other)]
+    Unknown,
+}
+
+// Fonctionnalités supportées par cette version de snake_protocol
+pub const SUPPORTED_CAPABILITIES: &[Capability] = &[
+    Capability::DeltaUpdates,
+    Capability::BinaryEncoding,
+    Capability::UdpUpdates,
+];
+
+// Nombre maximal de caractères du surnom d'un joueur
+pub const MAX_NAME_LEN: usize = 16;
+
+// Salon toujours disponible, utilisé quand le client n'en choisit pas
+pub const DEFAULT_ROOM: &str = "default";
+
+// Salon demandé par le client, désigné par son nom
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum RoomChoice {
+    // Ouvre un nouveau salon, refusé si le nom est déjà pris
+    Create(String),
+    // Rejoint un salon existant
+    Join(String),
+}
+
+impl RoomChoice {
+    pub fn name(&self) -> &str {
+        match self {
+            RoomChoice::Create(name) | RoomChoice::Join(name) => name,
+        }
+    }
+}
+
+// Rôle du client dans le salon
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Role {
+    // Contrôle des serpents
+    Player,
+    // Regarde la partie sans jouer, le nombre de spectateurs n'est pas limité
+    Spectator,
+}
+
+// Premier message envoyé par un client
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Handshake {
+    pub version: u32,
+    // Nombre de joueurs sur le client, 0 pour un spectateur
+    pub nb_players: u32,
+    pub capabilities: Vec<Capability>,
+    pub room: RoomChoice,
+    pub role: Role,
+    // Jeton d'une session précédente pour reprendre ses serpents
+    // après une coupure de connexion
+    pub session: Option<String>,
+    // Surnoms des joueurs du client, dans l'ordre des joueurs. Un joueur
+    // sans surnom porte le nom par défaut de son serpent
+    #[serde(default)]
+    pub names: Vec<String>,
+}
+
+// Session d'un joueur, permet de revenir dans la partie après une coupure
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Session {
+    pub token: String,
+    // Délai en secondes pendant lequel les serpents du joueur l'attendent
+    pub grace: u64,
+}
+
+// Réponse du serveur quand il accepte un client
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HandshakeAck {
+    pub version: u32,
+    // Numéros des serpents attribués aux joueurs du client. Vide pour un
+    // joueur arrivé pendant une partie sans place libre, il reçoit ses
+    // serpents avec ServerMsg::Seats au début d'une partie suivante
+    pub snakes: Vec<u32>,
+    // Fonctionnalités que le client et le serveur vont utiliser
+    pub capabilities: Vec<Capability>,
+    // None pour un spectateur ou si le serveur n'accepte pas les reconnexions
+    pub session: Option<Session>,
+    // Clé qui commence les datagrammes du client, si UdpUpdates est utilisé
+    pub udp_key: Option<u64>,
+}
+
+impl Handshake {
+    pub fn new(nb_players: u32, room: RoomChoice) -> Self {
+        Handshake {
+            version: PROTOCOL_VERSION,
+            nb_players,
+            capabilities: SUPPORTED_CAPABILITIES.to_vec(),
+            room,
+            role: Role::Player,
+            session: None,
+            names: vec![],
+        }
+    }
+
+    pub fn spectator(room: RoomChoice) -> Self {
+        Handshake {
+            version: PROTOCOL_VERSION,
+            nb_players: 0,
+            capabilities: SUPPORTED_CAPABILITIES.to_vec(),
+            room,
+            role: Role::Spectator,
+            session: None,
+            names: vec![],
+        }
+    }
+
+    // Fonctionnalités supportées à la fois par le client et par le serveur
+    pub fn negotiate(&self, server_capabilities: &[Capability]) -> Vec<Capability> {
+        server_capabilities
+            .iter()
+            .filter(|c| **c != Capability::Unknown && self.capabilities.contains(c))
+            .cloned()
+            .collect()
+    }
+}
+
+// Nom d'un serpent dont le joueur n'a pas choisi de surnom, ou d'un bot
+pub fn default_name(snake: u32) -> String {
+    format!("Snake {}", snake)
+}
+
+// Vérifie le surnom choisi par un joueur. Les noms par défaut sont
+// réservés pour qu'un joueur ne puisse pas se faire passer pour un autre serpent
+pub fn check_name(name: &str) -> Result<(), String> {
+    let len = name.chars().count();
+    if len == 0 || len > MAX_NAME_LEN {
+        return Err(format!(
+            "A nickname must have between 1 and {} characters",
+            MAX_NAME_LEN
+        ));
+    }
+    if !name
+        .chars()
+        .all(|c| c.is_alphanumeric() || c == ' ' || c == '-' || c == '_' || c == '.')
+        || name.trim() != name
+    {
+        return Err(String::from(
+            "A nickname can only contain letters, digits, inner spaces, '-', '_' and '.'",
+        ));
+    }
+    let reserved = name
+        .to_lowercase()
+        .strip_prefix("snake ")
+        .is_some_and(|n| n.parse::<u32>().is_ok());
+    if reserved {
+        return Err(format!("The nickname \"{}\" is reserved", name));
+    }
+    Ok(())
+}
+
+// Vérifie les surnoms d'un joueur qui arrive. Un surnom ne peut pas reprendre,
+// à la casse près, un surnom de _taken_ ou un autre surnom du même joueur
+pub fn check_new_names(names: &[String], taken: &[String]) -> Result<(), String> {
+    let mut taken: Vec<String> = taken.iter().map(|name| name.to_lowercase()).collect();
+    for name in names.iter() {
+        check_name(name)?;
+        if taken.contains(&name.to_lowercase()) {
+            return Err(format!("The nickname \"{}\" is already taken", name));
+        }
+        taken.push(name.to_lowercase());
+    }
+    Ok(())
+}
+
+// Retrouve la version du protocole d'un message Init, même quand il ne peut
+// pas être lu par cette version. Les clients d'avant la version 1 envoyaient
+// seulement leur nombre de joueurs
+pub fn handshake_version(frame: &[u8]) -> Option<u32> {
+    let init = serde_json::from_slice::<Value>(frame)
+        .ok()?
+        .get("Init")?
+        .clone();
+    if init.is_u64() {
+        return Some(0);
+    }
+    init.get("version")?.as_u64().map(|v| v as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn default_names_are_reserved() {
+        assert!(check_name(&default_name(3)).is_err());
+        assert!(check_name("snake 12").is_err());
+        assert!(check_name("SNAKE 1").is_err());
+        assert!(check_name("Snake").is_ok());
+        assert!(check_name("Snake one").is_ok());
+        assert!(check_name("Snake 1a").is_ok());
+    }
+
+    #[test]
+    fn name_length() {
+        assert!(check_name("").is_err());
+        assert!(check_name("a").is_ok());
+        assert!(check_name(&"a".repeat(MAX_NAME_LEN)).is_ok());
+        assert!(check_name(&"a".repeat(MAX_NAME_LEN + 1)).is_err());
+        // La longueur est comptée en caractères, pas en octets
+        assert!(check_name(&"é".repeat(MAX_NAME_LEN)).is_ok());
+    }
+
+    #[test]
+    fn name_charset() {
+        assert!(check_name("Jean-Luc_2.0").is_ok());
+        assert!(check_name("Jean Luc").is_ok());
+        assert!(check_name(" Jean").is_err());
+        assert!(check_name("Jean ").is_err());
+        assert!(check_name("Jean\tLuc").is_err());
+        assert!(check_name("Jean\x1b[31m").is_err());
+        assert!(check_name("<b>Jean</b>").is_err());
+    }
+
+    #[test]
+    fn names_unique_ignoring_case() {
+        let taken = names(&["Alice", "bob"]);
+        assert!(check_new_names(&names(&["Carol", "Dave"]), &taken).is_ok());
+        assert!(check_new_names(&names(&["alice"]), &taken).is_err());
+        assert!(check_new_names(&names(&["BOB"]), &taken).is_err());
+        assert!(check_new_names(&names(&["Carol", "CAROL"]), &taken).is_err());
+        assert!(check_new_names(&names(&["Carol", "Snake 2"]), &[]).is_err());
+    }
+}
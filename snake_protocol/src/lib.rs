@@ -0,0 +1,283 @@
+// Structures échangées entre snake_server et snake_client
+use serde::{Deserialize, Serialize};
+use std::net::{SocketAddr, ToSocketAddrs};
+
+mod datagram;
+mod delta;
+mod framing;
+mod handshake;
+pub mod snake;
+
+pub use datagram::*;
+pub use delta::*;
+pub use framing::*;
+pub use handshake::*;
+
+// Adresse utilisée quand ni l'option ni la variable d'environnement n'est donnée
+pub const DEFAULT_SERVER_ADDR: &str = "127.0.0.1:12345";
+// Taille maximale d'un message échangé entre le client et le serveur
+pub const MAX_FRAME_SIZE: usize = 1 << 20;
+// Nombre maximal de caractères d'un message du chat
+pub const MAX_CHAT_LEN: usize = 200;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum PlayerStatus {
+    Loser,
+    Leaver,
+    Player(u32), //Playing and current score
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Game {
+    // Number of snakes that will be in the game
+    pub nb_snakes: u32,
+    // If None means that the Snake has lost
+    pub snakes: Vec<Option<Snake>>,
+    // Ids of snakes controlled by bots
+    pub bots: Vec<u32>,
+    // Ids of snakes waiting for their player to reconnect, they do not move
+    pub frozen: Vec<u32>,
+    // Latence en millisecondes du joueur de chaque serpent, None pour les bots
+    pub latencies: Vec<Option<u32>>,
+    // Surnom du joueur de chaque serpent, ou nom par défaut
+    pub names: Vec<String>,
+    pub bots_difficulty: BotMovement,
+    pub points_to_win: u32,
+    // Points gagnés en mangeant
+    pub food_points: u32,
+
+    pub food: Point,
+    pub scores: Vec<PlayerStatus>,
+    pub speed: u64,
+    // Dimensions du terrain de jeu, bordures comprises
+    pub width: u16,
+    pub height: u16,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum BotMovement {
+    Random,
+    ToTheFood,
+    Survival,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Point {
+    pub x: u16,
+    pub y: u16,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+// Structure du serpent
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Snake {
+    pub id: u32,
+    pub head: Point,
+    // L'index de la queue est la valeur 0
+    pub body: Vec<Point>,
+    pub direction: Direction,
+}
+
+// Un client présent dans le salon avant le début de la partie
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LobbyPlayer {
+    // Numéros des serpents des joueurs du client
+    pub snakes: Vec<u32>,
+    // Surnoms des joueurs, dans l'ordre des serpents
+    pub names: Vec<String>,
+    pub ready: bool,
+    // Le créateur du salon, ou le plus ancien client encore là,
+    // peut lancer la partie sans attendre
+    pub host: bool,
+}
+
+// État du salon tant que la partie n'a pas commencé
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Lobby {
+    pub room: String,
+    // Nombre de serpents qui peuvent être contrôlés par des humains
+    pub seats: u32,
+    pub players: Vec<LobbyPlayer>,
+    pub spectators: u32,
+}
+
+// Numéro du tour d'un état de la partie envoyé à un client
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub struct Tick {
+    // Croissant pendant toute la vie du salon, d'une partie à l'autre
+    pub id: u64,
+    // Numéro de séquence de la dernière commande du client appliquée
+    // avant ce tour, 0 si aucune
+    pub last_input: u64,
+}
+
+// Message du chat relayé par le serveur à tous les clients du salon
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ChatMessage {
+    // Serpents des joueurs de l'auteur, vide pour un spectateur
+    pub snakes: Vec<u32>,
+    // Surnoms des joueurs de l'auteur, ou "Spectator"
+    pub author: String,
+    pub text: String,
+}
+
+// Vainqueur d'une partie
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Winner {
+    pub snake: u32,
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum ServerMsg {
+    InitAck(HandshakeAck),
+    // Envoyé à chaque arrivée, départ ou changement d'un client du salon
+    Lobby(Lobby),
+    Playing(Game, Vec<u32>, Tick),
+    // Changements depuis le message Playing ou Delta précédent, envoyé aux
+    // clients qui supportent Capability::DeltaUpdates
+    Delta(GameDelta, Vec<u32>, Tick),
+    // None en cas d'égalité
+    End(Option<Winner>),
+    Error(String),
+    // Le client doit répondre avec Pong et le même numéro, le serveur
+    // mesure ainsi la latence et détecte les clients qui ne répondent plus
+    Ping(u64),
+    Chat(ChatMessage),
+    // Serpents et session donnés à un joueur qui attendait une place
+    Seats(Vec<u32>, Option<Session>),
+    // Le serveur s'arrête, avec la raison, la connexion va être fermée
+    Shutdown(String),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum ClientMsg {
+    // Dis au serveur la version du client et le nombre de joueurs
+    Init(Handshake),
+    // Numéro de séquence croissant, à partir de 1, et directions demandées
+    // par chaque joueur dans l'ordre des touches
+    SnakeDirection(u64, Vec<Vec<Direction>>),
+    Leave(u32),
+    // Le client est prêt, ou ne l'est plus, à commencer la partie
+    Ready(bool),
+    // Demande de l'hôte pour commencer la partie, les places libres
+    // sont données à des bots
+    Start,
+    Pong(u64),
+    // Message pour les autres clients du salon, joueurs et spectateurs
+    Chat(String),
+}
+
+impl Game {
+    // Partie à son premier tour: chaque serpent à sa place de départ, les
+    // _nb_bots_ derniers joués par des bots. Les règles (vitesse, points)
+    // sont laissées à zéro, le serveur les prend dans sa configuration
+    pub fn new(nb_snakes: u32, nb_bots: u32, width: u16, height: u16, food: Point) -> Self {
+        Game {
+            nb_snakes,
+            snakes: (1..=nb_snakes)
+                .map(|id| Some(Snake::init(nb_snakes, id, width, height)))
+                .collect(),
+            bots: (nb_snakes.saturating_sub(nb_bots) + 1..=nb_snakes).collect(),
+            frozen: vec![],
+            latencies: vec![None; nb_snakes as usize],
+            names: (1..=nb_snakes).map(default_name).collect(),
+            bots_difficulty: BotMovement::ToTheFood,
+            points_to_win: 0,
+            food_points: 0,
+            food,
+            scores: vec![PlayerStatus::Player(0); nb_snakes as usize],
+            speed: 0,
+            width,
+            height,
+        }
+    }
+
+    // Surnom du joueur d'un serpent, ou son nom par défaut
+    pub fn name(&self, snake: u32) -> String {
+        self.names
+            .get(snake as usize - 1)
+            .cloned()
+            .unwrap_or_else(|| default_name(snake))
+    }
+}
+
+impl Point {
+    pub fn new(x: u16, y: u16) -> Self {
+        Point { x, y }
+    }
+
+    pub fn next_point(p: &Point, d: &Direction) -> Point {
+        match d {
+            Direction::Up => Point::new(p.x, p.y - 1),
+            Direction::Down => Point::new(p.x, p.y + 1),
+            Direction::Left => Point::new(p.x - 1, p.y),
+            Direction::Right => Point::new(p.x + 1, p.y),
+        }
+    }
+
+    pub fn sub(p1: &Point, p2: &Point) -> (i16, i16) {
+        (p1.x as i16 - p2.x as i16, p1.y as i16 - p2.y as i16)
+    }
+}
+
+// Texte d'un message du chat tel qu'il est relayé: sans caractères de
+// contrôle, qui pourraient piloter le terminal des autres clients, et
+// limité à MAX_CHAT_LEN caractères. None si le message est vide
+pub fn chat_text(text: &str) -> Option<String> {
+    let text: String = text.chars().filter(|c| !c.is_control()).collect();
+    let text: String = text.trim().chars().take(MAX_CHAT_LEN).collect();
+    (!text.is_empty()).then_some(text)
+}
+
+// Résout une adresse de la forme host:port, ipv4:port ou [ipv6]:port
+pub fn resolve_addr(addr: &str) -> Result<Vec<SocketAddr>, String> {
+    let addrs: Vec<SocketAddr> = addr
+        .to_socket_addrs()
+        .map_err(|e| {
+            format!(
+                "Invalid address \"{}\" ({}), expected host:port, e.g. {} or [::1]:12345",
+                addr, e, DEFAULT_SERVER_ADDR
+            )
+        })?
+        .collect();
+    if addrs.is_empty() {
+        return Err(format!("Address \"{}\" did not resolve to anything", addr));
+    }
+    Ok(addrs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chat_text_strips_control_chars() {
+        assert_eq!(
+            chat_text("he\x1b[2Jllo\r\n\tworld\x07"),
+            Some("he[2Jlloworld".to_string())
+        );
+        assert_eq!(chat_text("  salut  "), Some("salut".to_string()));
+    }
+
+    #[test]
+    fn chat_text_capped_in_chars() {
+        let long = "é".repeat(MAX_CHAT_LEN + 10);
+        let text = chat_text(&long).unwrap();
+        assert_eq!(text.chars().count(), MAX_CHAT_LEN);
+    }
+
+    #[test]
+    fn chat_text_empty_is_none() {
+        assert_eq!(chat_text(""), None);
+        assert_eq!(chat_text("   "), None);
+        assert_eq!(chat_text("\n\x1b\t"), None);
+    }
+}
@@ -0,0 +1,193 @@
+use crate::{Direction, Point, Snake};
+use std::collections::VecDeque;
+
+pub const INIT_BODY_SIZE: u32 = 3;
+// Nombre minimal de cases entre le circuit de départ et les bordures
+const MIN_SPAWN_INSET: u16 = 2;
+// Nombre maximal de virages en attente pour un serpent
+pub const MAX_QUEUED_TURNS: usize = 3;
+
+// Virages demandés par un joueur, appliqués un par tour pour que deux
+// touches appuyées pendant le même tour ne se remplacent pas
+#[derive(Debug, Clone, Default)]
+pub struct DirectionQueue {
+    directions: VecDeque<Direction>,
+}
+
+impl Direction {
+    pub fn opposite(&self) -> Direction {
+        match self {
+            Direction::Up => Direction::Down,
+            Direction::Down => Direction::Up,
+            Direction::Left => Direction::Right,
+            Direction::Right => Direction::Left,
+        }
+    }
+}
+
+impl DirectionQueue {
+    // Ajoute un virage à la suite de ceux en attente. Il est ignoré s'il ne
+    // change pas la direction qu'aura le serpent, _current_ si la file est
+    // vide, ou s'il lui fait faire demi-tour
+    pub fn push(&mut self, current: &Direction, d: Direction) {
+        let last = self.directions.back().unwrap_or(current);
+        if self.directions.len() >= MAX_QUEUED_TURNS || *last == d || last.opposite() == d {
+            return;
+        }
+        self.directions.push_back(d);
+    }
+
+    // Prochain virage, à appliquer au début d'un tour
+    pub fn pop(&mut self) -> Option<Direction> {
+        self.directions.pop_front()
+    }
+}
+
+impl Snake {
+    pub fn change_direction(&mut self, d: Direction) {
+        match (&self.direction, d) {
+            (Direction::Up, Direction::Down) => (),
+            (Direction::Down, Direction::Up) => (),
+            (Direction::Left, Direction::Right) => (),
+            (Direction::Right, Direction::Left) => (),
+            (_, d) => self.direction = d,
+        }
+    }
+
+    pub fn step(&mut self, grow: bool) {
+        // On modifie le corps du serpent
+        self.body.push(self.head.clone());
+        if !grow {
+            self.body.remove(0);
+        }
+
+        // On modifie la tête
+        self.head = Point::next_point(&self.head, &self.direction);
+    }
+
+    pub fn is_in_body(&self, p: &Point) -> bool {
+        for bp in self.body.iter() {
+            if bp == p {
+                return true;
+            }
+        }
+        false
+    }
+
+    pub fn is_player_nb(&self, id: u32) -> bool {
+        self.id == id
+    }
+
+    // Les serpents apparaissent à intervalles réguliers sur un circuit à
+    // l'intérieur du terrain et le parcourent dans le sens des aiguilles
+    // d'une montre, ils ont ainsi tous la même place devant eux
+    pub fn init(nb_players: u32, player_nb: u32, width: u16, height: u16) -> Self {
+        let path = spawn_loop(nb_players, width, height)
+            .expect("The board is too small for this number of snakes");
+        let len = path.len();
+        // Décalage d'un demi intervalle pour éviter de commencer dans un coin
+        let head_index = (2 * (player_nb as usize - 1) + 1) * len / (2 * nb_players as usize);
+        let (head, direction) = path[head_index].clone();
+        // L'index de la queue est la valeur 0
+        let body = (1..=INIT_BODY_SIZE as usize)
+            .rev()
+            .map(|i| path[(head_index + len - i) % len].0.clone())
+            .collect();
+
+        Snake {
+            id: player_nb,
+            head,
+            body,
+            direction,
+        }
+    }
+
+    // Le terrain est-il assez grand pour faire apparaître ce nombre de serpents
+    pub fn can_spawn(nb_players: u32, width: u16, height: u16) -> bool {
+        spawn_loop(nb_players, width, height).is_some()
+    }
+}
+
+// Cherche le circuit le plus éloigné des bordures qui laisse à chaque serpent
+// la place pour son corps, sa tête et une case vide
+fn spawn_loop(nb_players: u32, width: u16, height: u16) -> Option<Vec<(Point, Direction)>> {
+    let spacing = INIT_BODY_SIZE as usize + 2;
+    let max_inset = std::cmp::min(width, height) / 4;
+    (MIN_SPAWN_INSET..=max_inset)
+        .rev()
+        .map(|inset| rectangle_loop(inset, width, height))
+        .find(|path| path.len() >= nb_players as usize * spacing)
+}
+
+// Cases d'un rectangle à _inset_ cases des bordures, parcourues dans le sens
+// des aiguilles d'une montre. Chaque case est associée à la direction de la suivante
+fn rectangle_loop(inset: u16, width: u16, height: u16) -> Vec<(Point, Direction)> {
+    let (x0, y0) = (1 + inset, 1 + inset);
+    let (x1, y1) = (width.saturating_sub(inset), height.saturating_sub(inset));
+    let mut path = vec![];
+    if x1 <= x0 || y1 <= y0 {
+        return path;
+    }
+    for x in x0..x1 {
+        path.push((Point::new(x, y0), Direction::Right));
+    }
+    for y in y0..y1 {
+        path.push((Point::new(x1, y), Direction::Down));
+    }
+    for x in (x0 + 1..=x1).rev() {
+        path.push((Point::new(x, y1), Direction::Left));
+    }
+    for y in (y0 + 1..=y1).rev() {
+        path.push((Point::new(x0, y), Direction::Up));
+    }
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn drain(queue: &mut DirectionQueue) -> Vec<Direction> {
+        std::iter::from_fn(|| queue.pop()).collect()
+    }
+
+    #[test]
+    fn queue_keeps_three_turns() {
+        let mut queue = DirectionQueue::default();
+        for d in [
+            Direction::Up,
+            Direction::Left,
+            Direction::Down,
+            Direction::Right,
+        ] {
+            queue.push(&Direction::Right, d);
+        }
+        assert_eq!(
+            drain(&mut queue),
+            vec![Direction::Up, Direction::Left, Direction::Down]
+        );
+        assert_eq!(MAX_QUEUED_TURNS, 3);
+    }
+
+    #[test]
+    fn queue_rejects_reversal_of_last_queued_turn() {
+        let mut queue = DirectionQueue::default();
+        queue.push(&Direction::Right, Direction::Up);
+        // Demi-tour par rapport au virage en attente, pas à la direction actuelle
+        queue.push(&Direction::Right, Direction::Down);
+        // Demi-tour par rapport à la direction actuelle, permis après Up
+        queue.push(&Direction::Right, Direction::Left);
+        assert_eq!(drain(&mut queue), vec![Direction::Up, Direction::Left]);
+    }
+
+    #[test]
+    fn queue_rejects_reversal_and_repeat_of_current_direction() {
+        let mut queue = DirectionQueue::default();
+        queue.push(&Direction::Right, Direction::Left);
+        queue.push(&Direction::Right, Direction::Right);
+        assert!(queue.pop().is_none());
+        queue.push(&Direction::Right, Direction::Down);
+        queue.push(&Direction::Right, Direction::Down);
+        assert_eq!(drain(&mut queue), vec![Direction::Down]);
+    }
+}
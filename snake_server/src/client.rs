@@ -0,0 +1,385 @@
+use crate::log;
+use crate::room::RoomEvent;
+use crate::udp::UdpLink;
+use rand::Rng;
+use snake_protocol::*;
+use std::collections::VecDeque;
+use std::net::{Shutdown, SocketAddr, TcpStream};
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Intervalle entre deux pings envoyés à un client
+pub const PING_PERIOD: Duration = Duration::from_secs(1);
+// Un client peut envoyer au plus CHAT_BURST messages du chat par CHAT_PERIOD
+const CHAT_BURST: usize = 3;
+const CHAT_PERIOD: Duration = Duration::from_secs(5);
+
+// Un client connecté au serveur, il peut contrôler plusieurs serpents
+pub struct Client {
+    // Index du client dans son salon
+    id: usize,
+    // Numéros des serpents contrôlés par ce client
+    pub players: Vec<u32>,
+    // Surnoms des joueurs, dans l'ordre de _players_
+    pub names: Vec<String>,
+    // Handshake d'un joueur arrivé pendant une partie sans place libre,
+    // il regarde la partie en attendant une place dans la suivante
+    pub waiting: Option<Handshake>,
+    // None si le client s'est déconnecté
+    pub stream: Option<TcpStream>,
+    // Le client est prêt à commencer la partie
+    pub ready: bool,
+    // Jeton qui permet au client de reprendre ses serpents après une coupure
+    pub session: String,
+    // Moment de la perte de connexion, tant que le client peut revenir
+    pub lost_at: Option<Instant>,
+    // Numéro de la connexion en cours, incrémenté à chaque reconnexion
+    connection: u32,
+    // Fonctionnalités négociées lors du handshake
+    pub capabilities: Vec<Capability>,
+    // Le client a reçu l'état complet de la partie en cours et peut
+    // recevoir des deltas
+    pub synced: bool,
+    // Numéro de séquence de la dernière commande reçue, les commandes
+    // plus anciennes ou répétées sont ignorées
+    pub last_input: u64,
+    // Numéro et moment d'envoi du dernier ping
+    ping: u64,
+    pinged_at: Option<Instant>,
+    // Dernière réponse à un ping, ou début de la connexion
+    last_seen: Instant,
+    // Temps d'aller-retour mesuré avec le dernier ping
+    pub latency: Option<Duration>,
+    // Canal UDP pour l'état de la partie et les directions, si négocié
+    udp: Option<UdpLink>,
+    // Moments d'envoi des derniers messages du chat relayés
+    chat_sent: VecDeque<Instant>,
+    sender: Sender<RoomEvent>,
+}
+
+impl Client {
+    pub fn new(
+        id: usize,
+        players: Vec<u32>,
+        session: String,
+        capabilities: Vec<Capability>,
+        udp: Option<UdpLink>,
+        stream: TcpStream,
+        sender: Sender<RoomEvent>,
+    ) -> Self {
+        let mut client = Client {
+            id,
+            players,
+            names: vec![],
+            waiting: None,
+            stream: None,
+            ready: false,
+            session,
+            lost_at: None,
+            connection: 0,
+            capabilities,
+            synced: false,
+            last_input: 0,
+            ping: 0,
+            pinged_at: None,
+            last_seen: Instant::now(),
+            latency: None,
+            udp,
+            chat_sent: VecDeque::new(),
+            sender,
+        };
+        client.listen(stream);
+        client
+    }
+
+    // Lance un thread qui lit les messages du client et les transmet au
+    // salon avec l'index du client, pour ne jamais bloquer la partie
+    fn listen(&mut self, stream: TcpStream) {
+        let (id, connection, sender) = (self.id, self.connection, self.sender.clone());
+        let encoding = self.encoding();
+        let mut reader = stream.try_clone().expect("Could not clone client stream");
+        thread::spawn(move || loop {
+            match read_msg(&mut reader, encoding) {
+                Ok(msg) => {
+                    if sender.send(RoomEvent::Msg(id, msg)).is_err() {
+                        return;
+                    }
+                }
+                Err(e) => {
+                    log!("Client {} stopped sending messages: {}", id, e);
+                    let _ = sender.send(RoomEvent::Lost(id, connection));
+                    return;
+                }
+            }
+        });
+        self.stream = Some(stream);
+    }
+
+    // Nom du client dans les logs: les surnoms de ses joueurs
+    pub fn name(&self) -> String {
+        match (&self.waiting, self.names.is_empty()) {
+            (Some(_), _) => format!("Waiting player {}", self.id),
+            (None, true) => format!("Spectator {}", self.id),
+            (None, false) => self.names.join(", "),
+        }
+    }
+
+    // Un spectateur ne contrôle aucun serpent
+    pub fn is_player(&self) -> bool {
+        !self.players.is_empty()
+    }
+
+    // Le client a quitté le salon, il ne pourra pas revenir
+    pub fn disconnect(&mut self) {
+        if let Some(stream) = self.stream.take() {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+        self.lost_at = None;
+        self.udp = None;
+    }
+
+    // La connexion a été coupée sans que le client quitte le salon,
+    // un joueur peut revenir avec son jeton de session
+    pub fn connection_lost(&mut self, connection: u32) {
+        // Message d'une connexion déjà remplacée
+        if connection != self.connection || self.stream.is_none() {
+            return;
+        }
+        self.disconnect();
+        if self.is_player() {
+            self.lost_at = Some(Instant::now());
+        }
+    }
+
+    // Remplace la connexion du client, l'ancienne est fermée si elle
+    // n'a pas encore été détectée comme coupée
+    pub fn reconnect(
+        &mut self,
+        stream: TcpStream,
+        capabilities: Vec<Capability>,
+        udp: Option<UdpLink>,
+    ) {
+        if let Some(old) = self.stream.take() {
+            let _ = old.shutdown(Shutdown::Both);
+        }
+        self.connection += 1;
+        self.lost_at = None;
+        self.capabilities = capabilities;
+        self.udp = udp;
+        self.synced = false;
+        self.pinged_at = None;
+        self.last_seen = Instant::now();
+        self.listen(stream);
+    }
+
+    // Envoie un message au client, une erreur est traitée comme une coupure.
+    // Retourne true si le message a été envoyé
+    pub fn send(&mut self, msg: &ServerMsg) -> bool {
+        let encoding = self.encoding();
+        if let Some(stream) = &mut self.stream {
+            match send_msg_to_client(msg, stream, encoding) {
+                Ok(()) => return true,
+                Err(e) => {
+                    log!("Client disconnected: {}", e);
+                    self.connection_lost(self.connection);
+                }
+            }
+        }
+        false
+    }
+
+    // Envoie l'état de la partie par UDP si possible, par TCP sinon
+    pub fn send_state(&mut self, msg: &ServerMsg) -> bool {
+        let encoding = self.encoding();
+        match &self.udp {
+            Some(udp) if self.stream.is_some() && udp.send(msg, encoding) => true,
+            _ => self.send(msg),
+        }
+    }
+
+    pub fn uses_udp(&self) -> bool {
+        self.udp.is_some()
+    }
+
+    // Le client envoie ses datagrammes depuis cette adresse
+    pub fn datagram_from(&mut self, addr: SocketAddr) {
+        if let (Some(udp), Some(_)) = (&mut self.udp, &self.stream) {
+            udp.addr = Some(addr);
+        }
+    }
+
+    // Envoie un ping quand le précédent est assez ancien. Un client qui n'a
+    // pas répondu depuis _idle_timeout_ est déconnecté, retourne true dans ce cas
+    pub fn heartbeat(&mut self, idle_timeout: Option<Duration>) -> bool {
+        if self.stream.is_none() {
+            return false;
+        }
+        if idle_timeout.is_some_and(|timeout| self.last_seen.elapsed() >= timeout) {
+            log!("{} timed out", self.name());
+            self.disconnect();
+            return true;
+        }
+        if self
+            .pinged_at
+            .is_none_or(|pinged_at| pinged_at.elapsed() >= PING_PERIOD)
+        {
+            self.ping += 1;
+            self.pinged_at = Some(Instant::now());
+            self.send(&ServerMsg::Ping(self.ping));
+        }
+        false
+    }
+
+    // Réponse à un ping, seule celle du dernier ping mesure la latence
+    pub fn pong(&mut self, ping: u64) {
+        self.last_seen = Instant::now();
+        if let Some(pinged_at) = self.pinged_at.filter(|_| ping == self.ping) {
+            self.latency = Some(pinged_at.elapsed());
+        }
+    }
+
+    // Retourne false si le client a déjà envoyé trop de messages du chat
+    // récemment, sinon compte ce message
+    pub fn allow_chat(&mut self) -> bool {
+        allow_chat_at(&mut self.chat_sent, Instant::now())
+    }
+
+    pub fn uses(&self, capability: Capability) -> bool {
+        self.capabilities.contains(&capability)
+    }
+
+    // Format des messages après le handshake
+    pub fn encoding(&self) -> Encoding {
+        Encoding::negotiated(&self.capabilities)
+    }
+
+    // Le client a dépassé le délai pour revenir
+    pub fn session_expired(&self, grace: Duration) -> bool {
+        self.lost_at
+            .is_some_and(|lost_at| lost_at.elapsed() >= grace)
+    }
+
+    // Commande par défaut d'un client: garder la même direction, ou quitter
+    // si il est déconnecté et ne peut plus revenir
+    pub fn default_input(&self) -> ClientMsg {
+        match (&self.stream, self.lost_at) {
+            (None, None) => ClientMsg::Leave(self.players.len() as u32),
+            _ => ClientMsg::SnakeDirection(self.last_input, vec![vec![]; self.players.len()]),
+        }
+    }
+}
+
+// Jeton de session aléatoire, difficile à deviner pour un autre client
+pub fn new_session() -> String {
+    format!("{:032x}", rand::thread_rng().gen::<u128>())
+}
+
+// Ajoute un message reçu aux commandes du tour, les directions reçues
+// pour chaque serpent sont gardées dans l'ordre
+pub fn merge_input(input: &mut ClientMsg, msg: ClientMsg, client: &mut Client) {
+    match msg {
+        ClientMsg::SnakeDirection(sequence, _) if sequence <= client.last_input => {
+            log!(
+                "Ignoring input {} of {}, input {} already received",
+                sequence,
+                client.name(),
+                client.last_input
+            );
+        }
+        ClientMsg::SnakeDirection(sequence, directions) => {
+            client.last_input = sequence;
+            if let ClientMsg::SnakeDirection(last, current) = input {
+                *last = sequence;
+                for (current, directions) in current.iter_mut().zip(directions) {
+                    current.extend(directions);
+                }
+            }
+        }
+        ClientMsg::Leave(nb_players) => {
+            client.disconnect();
+            *input = ClientMsg::Leave(nb_players);
+        }
+        ClientMsg::Init(_) => {
+            log!("Client sent an Init message during the game, ignoring it");
+        }
+        ClientMsg::Pong(ping) => client.pong(ping),
+        // Le chat est relayé par le salon, il n'a pas d'effet sur la partie
+        ClientMsg::Chat(_) => (),
+        // La partie a déjà commencé
+        ClientMsg::Ready(_) | ClientMsg::Start => (),
+    }
+}
+
+// Envoie un message aux différents clients
+pub fn send_msg_to_clients(msg: ServerMsg, clients: &mut [Client]) {
+    // For debugging purpose
+    //log!("Sending message to clients:\n {:?}", msg);
+    for client in clients.iter_mut() {
+        client.send(&msg);
+    }
+}
+
+pub fn send_msg_to_client(
+    msg: &ServerMsg,
+    client: &mut TcpStream,
+    encoding: Encoding,
+) -> Result<(), std::io::Error> {
+    write_msg(client, msg, encoding)
+}
+
+// Oublie les messages du chat envoyés il y a plus de CHAT_PERIOD, puis
+// compte celui envoyé à _now_ s'il en reste moins de CHAT_BURST
+fn allow_chat_at(chat_sent: &mut VecDeque<Instant>, now: Instant) -> bool {
+    while chat_sent
+        .front()
+        .is_some_and(|sent| now.duration_since(*sent) >= CHAT_PERIOD)
+    {
+        chat_sent.pop_front();
+    }
+    if chat_sent.len() >= CHAT_BURST {
+        return false;
+    }
+    chat_sent.push_back(now);
+    true
+}
+
+// Envoie la raison du refus au client avant de fermer la connexion.
+// Le client attend encore la réponse à son handshake, en JSON
+pub fn reject_client(stream: &mut TcpStream, msg: String) {
+    if send_msg_to_client(&ServerMsg::Error(msg), stream, Encoding::Json).is_ok() {
+        let _ = stream.shutdown(Shutdown::Both);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chat_burst_then_refused() {
+        let mut sent = VecDeque::new();
+        let now = Instant::now();
+        for _ in 0..CHAT_BURST {
+            assert!(allow_chat_at(&mut sent, now));
+        }
+        assert!(!allow_chat_at(&mut sent, now));
+        // Un message refusé n'est pas compté
+        assert_eq!(sent.len(), CHAT_BURST);
+    }
+
+    #[test]
+    fn chat_allowed_again_after_period() {
+        let mut sent = VecDeque::new();
+        let start = Instant::now();
+        let second = Duration::from_secs(1);
+        assert!(allow_chat_at(&mut sent, start));
+        assert!(allow_chat_at(&mut sent, start + second));
+        assert!(allow_chat_at(&mut sent, start + 2 * second));
+        assert!(!allow_chat_at(&mut sent, start + CHAT_PERIOD - second));
+        // Le premier message expire, les deux autres comptent encore
+        assert!(allow_chat_at(&mut sent, start + CHAT_PERIOD));
+        assert!(!allow_chat_at(&mut sent, start + CHAT_PERIOD));
+        assert!(allow_chat_at(&mut sent, start + CHAT_PERIOD + second));
+    }
+}
@@ -0,0 +1,242 @@
+use clap::Parser;
+use serde::{de, Deserialize, Deserializer};
+use snake_protocol::{BotMovement, DEFAULT_SERVER_ADDR};
+use std::fs;
+use std::path::PathBuf;
+
+// Valeurs par défaut des paramètres de la partie
+const NB_SNAKES: u32 = 4;
+const SPEED: u64 = 150;
+const POINTS: u32 = 10;
+const POINTS_TO_WIN: u32 = 50;
+pub const MAX_SNAKE_NB: u32 = 16;
+const WIDTH: u16 = 40;
+const HEIGHT: u16 = 21;
+const RECONNECT_GRACE: u64 = 10;
+const IDLE_TIMEOUT: u64 = 10;
+// Dimensions acceptées pour le terrain
+pub const MIN_WIDTH: u16 = 20;
+pub const MIN_HEIGHT: u16 = 10;
+pub const MAX_WIDTH: u16 = 200;
+pub const MAX_HEIGHT: u16 = 100;
+
+#[derive(Parser)]
+#[command(about = "Server of the multiplayer snake game")]
+struct Args {
+    /// Number of snakes in the game [default: 4]
+    #[arg(conflicts_with = "snakes")]
+    nb_snakes: Option<u32>,
+    /// Number of snakes controlled by bots [default: all snakes but one]
+    #[arg(conflicts_with = "bots")]
+    nb_bots: Option<u32>,
+    /// TOML file with the game parameters, the other options take precedence
+    #[arg(short, long)]
+    config: Option<PathBuf>,
+    /// Address to listen on, e.g. 0.0.0.0:12345 or [::]:12345 [default: 127.0.0.1:12345]
+    #[arg(long, env = "SNAKE_BIND")]
+    bind: Option<String>,
+    /// Address to serve the web client and its WebSocket gateway on, e.g. 127.0.0.1:8080
+    #[arg(long, env = "SNAKE_WEB")]
+    web: Option<String>,
+    /// Number of snakes in the game
+    #[arg(long)]
+    snakes: Option<u32>,
+    /// Number of snakes controlled by bots
+    #[arg(long)]
+    bots: Option<u32>,
+    /// Duration of a turn in milliseconds
+    #[arg(long)]
+    speed: Option<u64>,
+    /// Points earned by eating a food
+    #[arg(long)]
+    points: Option<u32>,
+    /// Score to reach to win the game
+    #[arg(long)]
+    points_to_win: Option<u32>,
+    /// Maximum number of snakes allowed in a game
+    #[arg(long)]
+    max_snakes: Option<u32>,
+    /// Width of the board, borders included
+    #[arg(long)]
+    width: Option<u16>,
+    /// Height of the board, borders included
+    #[arg(long)]
+    height: Option<u16>,
+    /// Movement of the bots: random, to-the-food or survival
+    #[arg(long, value_parser = parse_bot_movement)]
+    bots_difficulty: Option<BotMovement>,
+    /// Movement of the bots which take over the snakes of the players who
+    /// leave a game, without this option their snakes are removed
+    #[arg(long, value_parser = parse_bot_movement)]
+    leaver_bot: Option<BotMovement>,
+    /// Seconds a disconnected player has to come back, 0 to disable reconnection
+    #[arg(long)]
+    reconnect_grace: Option<u64>,
+    /// Seconds without answer before a client is disconnected, 0 to disable
+    #[arg(long)]
+    idle_timeout: Option<u64>,
+}
+
+// Paramètres du serveur et des parties, le fichier de configuration
+// n'a pas besoin de tous les définir
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub bind: String,
+    // Adresse du client web, désactivé par défaut
+    pub web: Option<String>,
+    pub nb_snakes: u32,
+    // Par défaut tous les serpents sauf un sont des bots
+    pub nb_bots: Option<u32>,
+    pub speed: u64,
+    pub points: u32,
+    pub points_to_win: u32,
+    pub max_snakes: u32,
+    pub width: u16,
+    pub height: u16,
+    #[serde(deserialize_with = "bot_movement")]
+    pub bots_difficulty: BotMovement,
+    // Mouvement des bots qui reprennent les serpents des joueurs partis,
+    // None pour retirer ces serpents de la partie
+    #[serde(deserialize_with = "leaver_bot")]
+    pub leaver_bot: Option<BotMovement>,
+    // Délai en secondes pour qu'un joueur déconnecté reprenne ses serpents
+    pub reconnect_grace: u64,
+    // Délai en secondes sans réponse aux pings avant de déconnecter un client
+    pub idle_timeout: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            bind: DEFAULT_SERVER_ADDR.to_string(),
+            web: None,
+            nb_snakes: NB_SNAKES,
+            nb_bots: None,
+            speed: SPEED,
+            points: POINTS,
+            points_to_win: POINTS_TO_WIN,
+            max_snakes: MAX_SNAKE_NB,
+            width: WIDTH,
+            height: HEIGHT,
+            bots_difficulty: BotMovement::ToTheFood,
+            leaver_bot: None,
+            reconnect_grace: RECONNECT_GRACE,
+            idle_timeout: IDLE_TIMEOUT,
+        }
+    }
+}
+
+impl Config {
+    pub fn nb_bots(&self) -> u32 {
+        self.nb_bots
+            .unwrap_or_else(|| self.nb_snakes.saturating_sub(1))
+    }
+
+    pub fn nb_humans(&self) -> u32 {
+        self.nb_snakes.saturating_sub(self.nb_bots())
+    }
+
+    // Les options de la ligne de commande remplacent celles du fichier
+    fn merge(&mut self, args: Args) {
+        if let Some(bind) = args.bind {
+            self.bind = bind;
+        }
+        if let Some(web) = args.web {
+            self.web = Some(web);
+        }
+        if let Some(nb_snakes) = args.nb_snakes.or(args.snakes) {
+            self.nb_snakes = nb_snakes;
+            // Le nombre de bots du fichier ne correspond plus
+            self.nb_bots = None;
+        }
+        if let Some(nb_bots) = args.nb_bots.or(args.bots) {
+            self.nb_bots = Some(nb_bots);
+        }
+        if let Some(speed) = args.speed {
+            self.speed = speed;
+        }
+        if let Some(points) = args.points {
+            self.points = points;
+        }
+        if let Some(points_to_win) = args.points_to_win {
+            self.points_to_win = points_to_win;
+        }
+        if let Some(max_snakes) = args.max_snakes {
+            self.max_snakes = max_snakes;
+        }
+        if let Some(width) = args.width {
+            self.width = width;
+        }
+        if let Some(height) = args.height {
+            self.height = height;
+        }
+        if let Some(bots_difficulty) = args.bots_difficulty {
+            self.bots_difficulty = bots_difficulty;
+        }
+        if let Some(leaver_bot) = args.leaver_bot {
+            self.leaver_bot = Some(leaver_bot);
+        }
+        if let Some(reconnect_grace) = args.reconnect_grace {
+            self.reconnect_grace = reconnect_grace;
+        }
+        if let Some(idle_timeout) = args.idle_timeout {
+            self.idle_timeout = idle_timeout;
+        }
+    }
+}
+
+// Lit la configuration du serveur à partir de la ligne de commande
+// et du fichier de configuration éventuel
+pub fn load() -> Result<Config, String> {
+    let args = Args::parse();
+    let mut config = match &args.config {
+        Some(path) => {
+            let content = fs::read_to_string(path)
+                .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+            toml::from_str(&content)
+                .map_err(|e| format!("Invalid configuration file {}: {}", path.display(), e))?
+        }
+        None => Config::default(),
+    };
+    config.merge(args);
+    Ok(config)
+}
+
+fn parse_bot_movement(s: &str) -> Result<BotMovement, String> {
+    match s.to_lowercase().replace(['-', '_'], "").as_str() {
+        "random" => Ok(BotMovement::Random),
+        "tothefood" => Ok(BotMovement::ToTheFood),
+        "survival" => Ok(BotMovement::Survival),
+        _ => Err(String::from("expected random, to-the-food or survival")),
+    }
+}
+
+// Le fichier de configuration accepte les mêmes noms que la ligne de commande
+fn bot_movement<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BotMovement, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    parse_bot_movement(&s).map_err(de::Error::custom)
+}
+
+fn leaver_bot<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<BotMovement>, D::Error> {
+    bot_movement(deserializer).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_bot_names_in_file_and_command_line() {
+        for name in ["to-the-food", "ToTheFood", "to_the_food"] {
+            let config: Config =
+                toml::from_str(&format!("bots_difficulty = \"{}\"", name)).unwrap();
+            assert_eq!(config.bots_difficulty, BotMovement::ToTheFood);
+            assert_eq!(parse_bot_movement(name), Ok(BotMovement::ToTheFood));
+        }
+        let config: Config = toml::from_str("leaver_bot = \"survival\"").unwrap();
+        assert_eq!(config.leaver_bot, Some(BotMovement::Survival));
+        assert_eq!(Config::default().leaver_bot, None);
+        assert!(toml::from_str::<Config>("bots_difficulty = \"smart\"").is_err());
+    }
+}
@@ -1,42 +1,20 @@
+use crate::client::PING_PERIOD;
+use crate::config::{Config, MAX_HEIGHT, MAX_SNAKE_NB, MAX_WIDTH, MIN_HEIGHT, MIN_WIDTH};
 use crate::log;
-use crate::shared_structures::ClientMsg::*;
-use crate::shared_structures::*;
-use crate::snake;
+use crate::snake::Bot;
 use rand::Rng;
-use std::fs::{File, OpenOptions};
+use snake_protocol::snake;
+use snake_protocol::ClientMsg::*;
+use snake_protocol::*;
+use std::fs::OpenOptions;
 use std::io::Write;
 
-const SPEED: u64 = 150;
-const POINTS: u32 = 10;
-const LOG_FILE: &'static str = "log";
-const MAX_SNAKE_NB: u32 = 4;
-const POINTS_TO_WIN: u32 = 50;
-
-impl Point {
-    pub fn new(x: u16, y: u16) -> Self {
-        Point { x, y }
-    }
-
-    pub fn next_point(p: &Point, d: &Direction) -> Point {
-        match d {
-            Direction::Up => Point::new(p.x, p.y - 1),
-            Direction::Down => Point::new(p.x, p.y + 1),
-            Direction::Left => Point::new(p.x - 1, p.y),
-            Direction::Right => Point::new(p.x + 1, p.y),
-        }
-    }
-
-    // Génère alétoirement un point dans l'espace du jeu
-    // où sera placée la prochaine pomme
-    pub fn random() -> Self {
-        let rx = rand::thread_rng().gen_range(2, WIDTH);
-        let ry = rand::thread_rng().gen_range(2, HEIGHT);
-        Point::new(rx as u16, ry as u16)
-    }
-
-    pub fn sub(p1: &Point, p2: &Point) -> (i16, i16) {
-        (p1.x as i16 - p2.x as i16, p1.y as i16 - p2.y as i16)
-    }
+// Génère alétoirement un point dans l'espace du jeu
+// où sera placée la prochaine pomme
+fn random_point(width: u16, height: u16) -> Point {
+    let rx = rand::thread_rng().gen_range(2, width);
+    let ry = rand::thread_rng().gen_range(2, height);
+    Point::new(rx, ry)
 }
 
 pub enum TurnOutcome {
@@ -46,12 +24,63 @@ pub enum TurnOutcome {
     End(Option<u32>),
 }
 
-impl Game {
-    // Initialise une structure Game
-    pub fn init(nb_snakes: u32, nb_bots: u32) -> Result<Self, String> {
-        // Maximum 4 serpents
-        if nb_snakes > MAX_SNAKE_NB {
-            return Err(format!("Maximum {} snakes", MAX_SNAKE_NB));
+// Commande d'un client associée aux numéros des serpents qu'il contrôle
+pub type ClientInput = (Vec<u32>, ClientMsg);
+
+// État d'une partie gardé par le serveur, il n'est pas envoyé aux clients
+pub struct ServerState {
+    // Virages en attente de chaque serpent
+    pub queues: Vec<snake::DirectionQueue>,
+    // Mouvement des bots qui reprennent les serpents des joueurs partis,
+    // None si ces serpents sont retirés de la partie
+    pub leaver_bot: Option<BotMovement>,
+    // Serpents repris par ces bots
+    pub replaced: Vec<u32>,
+    // Fichier de log du salon, complété à chaque partie
+    pub log_file: String,
+}
+
+impl ServerState {
+    pub fn new(
+        nb_snakes: u32,
+        leaver_bot: Option<BotMovement>,
+        replaced: Vec<u32>,
+        room: &str,
+    ) -> Self {
+        ServerState {
+            queues: vec![snake::DirectionQueue::default(); nb_snakes as usize],
+            leaver_bot,
+            replaced,
+            log_file: format!("{}.log", room),
+        }
+    }
+}
+
+// Règles du jeu appliquées par le serveur sur l'état partagé avec les clients
+pub trait GameLogic: Sized {
+    fn init(config: &Config) -> Result<Self, String>;
+    fn handle_inputs(&mut self, inputs: Vec<ClientInput>, state: &mut ServerState) -> Vec<u32>;
+    fn move_snake_bots(&mut self, state: &ServerState);
+    fn replace_leavers(&mut self, leavers: Vec<u32>, state: &mut ServerState) -> Vec<u32>;
+    fn turn(&mut self, inputs: Vec<ClientInput>, state: &mut ServerState) -> TurnOutcome;
+    fn check_collisions(&mut self, state: &ServerState) -> Vec<u32>;
+    fn add_snake(&mut self, id: u32, state: &mut ServerState) -> bool;
+    fn record_end(&self, outcome: &str, state: &ServerState);
+}
+
+impl GameLogic for Game {
+    // Initialise une structure Game et vérifie la configuration
+    fn init(config: &Config) -> Result<Self, String> {
+        let nb_snakes = config.nb_snakes;
+        let nb_bots = config.nb_bots();
+
+        // Le terrain ne peut pas accueillir plus de serpents
+        if config.max_snakes > MAX_SNAKE_NB {
+            return Err(format!("max_snakes cannot be more than {}", MAX_SNAKE_NB));
+        }
+
+        if nb_snakes > config.max_snakes {
+            return Err(format!("Maximum {} snakes", config.max_snakes));
         }
 
         // Il doit y avoir au moins 2 serpents
@@ -64,70 +93,98 @@ impl Game {
             return Err(String::from("At least 1 human player is needed"));
         }
 
-        // Efface le contenu du fichier de log
-        File::create(LOG_FILE).unwrap();
-        let mut snakes = vec![];
-        let mut scores = vec![];
-        let mut bots = vec![];
+        if !(MIN_WIDTH..=MAX_WIDTH).contains(&config.width)
+            || !(MIN_HEIGHT..=MAX_HEIGHT).contains(&config.height)
+        {
+            return Err(format!(
+                "The board should be between {}x{} and {}x{}",
+                MIN_WIDTH, MIN_HEIGHT, MAX_WIDTH, MAX_HEIGHT
+            ));
+        }
 
-        for player_nb in 1..nb_snakes + 1 {
-            if player_nb > (nb_snakes - nb_bots) {
-                bots.push(player_nb);
-            }
-            snakes.push(Some(Snake::init(nb_snakes, player_nb)));
-            scores.push(PlayerStatus::Player(0));
+        if !Snake::can_spawn(nb_snakes, config.width, config.height) {
+            return Err(format!(
+                "The board is too small for {} snakes, try a bigger width or height",
+                nb_snakes
+            ));
+        }
+
+        if config.speed == 0 {
+            return Err(String::from("speed should be at least 1 millisecond"));
+        }
+
+        // Un client doit avoir le temps de répondre à plusieurs pings
+        if config.idle_timeout != 0 && config.idle_timeout < 2 * PING_PERIOD.as_secs() {
+            return Err(format!(
+                "idle_timeout should be 0 or at least {} seconds",
+                2 * PING_PERIOD.as_secs()
+            ));
+        }
+
+        if config.points == 0 || config.points_to_win == 0 {
+            return Err(String::from("points and points_to_win should be positive"));
         }
 
         Ok(Game {
-            points_to_win: POINTS_TO_WIN,
-            nb_snakes: nb_snakes,
-            snakes: snakes,
-            food: Point::random(),
-            bots: bots,
-            bots_difficulty: BotMovement::ToTheFood,
-            scores: scores,
-            speed: SPEED,
+            points_to_win: config.points_to_win,
+            food_points: config.points,
+            bots_difficulty: config.bots_difficulty.clone(),
+            speed: config.speed,
+            ..Game::new(
+                nb_snakes,
+                nb_bots,
+                config.width,
+                config.height,
+                random_point(config.width, config.height),
+            )
         })
     }
 
     // Change la direction des serpents selon les commandes reçues
     // Si retourne [1,2] les joueurs 1 et 2 ont quitté
-    fn handle_inputs(&mut self, inputs: Vec<ClientMsg>) -> Vec<u32> {
+    fn handle_inputs(&mut self, inputs: Vec<ClientInput>, state: &mut ServerState) -> Vec<u32> {
         let mut leavers = vec![];
-        let mut player: u32 = 0;
-        //Pour chaque commande d'un joueur
-        for input in inputs.into_iter() {
+        //Pour chaque commande d'un client
+        for (players, input) in inputs.into_iter() {
             match input {
-                SnakeDirection(v) => {
-                    for command in v.into_iter() {
-                        if let Some(snake) = &mut self.snakes[player as usize] {
-                            match command {
-                                None => (),
-                                Some(d) => snake.change_direction(d),
+                SnakeDirection(_, v) => {
+                    for (player, commands) in players.iter().zip(v) {
+                        let i = *player as usize - 1;
+                        if let (Some(snake), Some(queue)) =
+                            (&self.snakes[i], state.queues.get_mut(i))
+                        {
+                            for d in commands {
+                                queue.push(&snake.direction, d);
                             }
                         }
-                        player += 1;
-                    }
-                }
-                Leave(nb_players) => {
-                    for _ in 0..nb_players {
-                        leavers.push(1 + player);
-                        player += 1;
                     }
                 }
+                Leave(_) => leavers.extend(players),
+                // Messages du salon ou de connexion, sans effet pendant la partie
+                Ready(_) | Start | Pong(_) | Chat(_) => (),
                 Init(_) => {
                     panic!("Client should not have sent an Init message at this stage");
                 }
             }
         }
+        // Un seul virage par tour, les suivants attendent les tours d'après
+        for (snake, queue) in self.snakes.iter_mut().zip(state.queues.iter_mut()) {
+            if let (Some(snake), Some(d)) = (snake, queue.pop()) {
+                snake.change_direction(d);
+            }
+        }
         leavers
     }
 
     // Algorithme qui fait bouger les bots
-    fn move_snake_bots(&mut self) {
+    fn move_snake_bots(&mut self, state: &ServerState) {
         for bot in self.bots.iter() {
+            let movement = match state.replaced.contains(bot) {
+                true => state.leaver_bot.as_ref().unwrap_or(&self.bots_difficulty),
+                false => &self.bots_difficulty,
+            };
             if let Some(snake) = &mut self.snakes[*bot as usize - 1] {
-                match self.bots_difficulty {
+                match movement {
                     // Si les bots bougent alétoirement
                     BotMovement::Random => snake.move_randomly(),
 
@@ -141,44 +198,74 @@ impl Game {
         }
     }
 
+    // Les serpents encore en vie des joueurs partis sont donnés à des bots,
+    // si le serveur le permet. Retourne les serpents à retirer de la partie
+    fn replace_leavers(&mut self, leavers: Vec<u32>, state: &mut ServerState) -> Vec<u32> {
+        if state.leaver_bot.is_none() {
+            return leavers;
+        }
+        let mut removed = vec![];
+        for leaver in leavers {
+            let i = leaver as usize - 1;
+            // Le client parti continue d'envoyer Leave pour ce serpent
+            if self.bots.contains(&leaver) {
+                continue;
+            }
+            if self.snakes[i].is_none() {
+                removed.push(leaver);
+                continue;
+            }
+            log!("{} has left, a bot takes over", self.name(leaver));
+            self.bots.push(leaver);
+            state.replaced.push(leaver);
+            state.queues[i] = snake::DirectionQueue::default();
+        }
+        removed
+    }
+
     // Si retoune None un joueur a quitté la partir
     // Si on retoune _Some([1])_, le joueur 1 a perdu
-    pub fn turn(&mut self, inputs: Vec<ClientMsg>) -> TurnOutcome {
+    fn turn(&mut self, inputs: Vec<ClientInput>, state: &mut ServerState) -> TurnOutcome {
         let mut has_eaten = false;
 
         // Récupère les touches
-        let leavers = self.handle_inputs(inputs);
+        let leavers = self.handle_inputs(inputs, state);
+        let leavers = self.replace_leavers(leavers, state);
 
         // Fais bouger les bots
-        self.move_snake_bots();
+        self.move_snake_bots(state);
 
-        // Fais mouvoir les serpents
+        // Fais mouvoir les serpents, ceux qui attendent leur joueur restent sur place
+        let frozen = &self.frozen;
         for (i, snake) in self.snakes.iter_mut().enumerate() {
-            if let Some(snake) = snake {
+            if let Some(snake) = snake.as_mut().filter(|s| !frozen.contains(&s.id)) {
                 let is_gonna_eat = (Point::next_point(&snake.head, &snake.direction)) == self.food;
                 snake.step(is_gonna_eat);
 
                 if is_gonna_eat {
                     if let PlayerStatus::Player(points) = self.scores[i] {
-                        self.scores[i] = PlayerStatus::Player(POINTS + points);
+                        self.scores[i] = PlayerStatus::Player(self.food_points + points);
                     }
                     has_eaten = true;
                 }
 
                 // Vérification
                 if let PlayerStatus::Player(points) = self.scores[i] {
-                    assert!((snake.body.len() as u32 - snake::INIT_BODY_SIZE) * 10 == points);
+                    assert!(
+                        (snake.body.len() as u32 - snake::INIT_BODY_SIZE) * self.food_points
+                            == points
+                    );
                 }
             }
         }
 
         // Si un serpent a mangé, régénérer de la nourriture
         if has_eaten {
-            self.food = Point::random()
+            self.food = random_point(self.width, self.height)
         }
 
         // _losers_ contient les serpents perdants
-        let losers = self.check_collisions();
+        let losers = self.check_collisions(state);
 
         let mut leavers_losers: Vec<u32> = vec![];
         leavers_losers.extend(&losers);
@@ -197,20 +284,17 @@ impl Game {
             }
 
             if losers.contains(l) {
-                log!("Serpent {} has lost!", l);
+                log!("{} has lost!", self.name(*l));
                 self.scores[(l - 1) as usize] = PlayerStatus::Loser;
             }
         }
 
         // Si un joueur a atteint le score objectif il gagne
         for (player, score) in self.scores.iter().enumerate() {
-            match score {
-                PlayerStatus::Player(points) => {
-                    if *points >= self.points_to_win {
-                        return TurnOutcome::End(Some(player as u32 + 1));
-                    }
+            if let PlayerStatus::Player(points) = score {
+                if *points >= self.points_to_win {
+                    return TurnOutcome::End(Some(player as u32 + 1));
                 }
-                _ => (),
             }
         }
 
@@ -235,13 +319,11 @@ impl Game {
     }
 
     // Check for collisions and return array of losing players
-    pub fn check_collisions(&mut self) -> Vec<u32> {
+    fn check_collisions(&mut self, state: &ServerState) -> Vec<u32> {
         let mut losers = vec![];
         let mut snakes_alive: Vec<&Snake> = vec![];
-        for snake in self.snakes.iter() {
-            if let Some(snake) = snake {
-                snakes_alive.push(snake);
-            }
+        for snake in self.snakes.iter().flatten() {
+            snakes_alive.push(snake);
         }
 
         for snake in snakes_alive.iter() {
@@ -249,7 +331,7 @@ impl Game {
             for other_snake in snakes_alive.iter() {
                 // Collisions tête - corps
                 if other_snake.is_in_body(&snake.head) {
-                    log_in_file("Is in body!\n".to_owned());
+                    log_in_file(&state.log_file, "Is in body!\n".to_owned());
                     losers.push(snake.id);
                 }
 
@@ -262,8 +344,8 @@ impl Game {
             // Teste les collisions avec les bordures
             if snake.head.x <= 1
                 || snake.head.y <= 1
-                || snake.head.x >= (WIDTH as u16)
-                || snake.head.y >= (HEIGHT as u16)
+                || snake.head.x >= self.width
+                || snake.head.y >= self.height
             {
                 losers.push(snake.id);
             }
@@ -272,10 +354,80 @@ impl Game {
         losers.dedup();
         losers
     }
+
+    // Fait apparaître le serpent _id_ en ligne droite dans un espace libre,
+    // avec autant de cases vides devant lui que son corps et une rangée vide
+    // de chaque côté. Retourne false si le terrain n'a pas d'espace assez grand
+    fn add_snake(&mut self, id: u32, state: &mut ServerState) -> bool {
+        let len = snake::INIT_BODY_SIZE as u16;
+        let span = 2 * len + 1;
+        let occupied = |p: &Point| {
+            *p == self.food
+                || self
+                    .snakes
+                    .iter()
+                    .flatten()
+                    .any(|snake| snake.head == *p || snake.is_in_body(p))
+        };
+        let mut spots = vec![];
+        for y in 3..=self.height.saturating_sub(2) {
+            for x in 2..=self.width.saturating_sub(span) {
+                let free =
+                    (x..x + span).all(|x| (y - 1..=y + 1).all(|y| !occupied(&Point::new(x, y))));
+                if free {
+                    spots.push((x, y));
+                }
+            }
+        }
+        if spots.is_empty() {
+            return false;
+        }
+        let mut rng = rand::thread_rng();
+        let (x, y) = spots[rng.gen_range(0, spots.len())];
+        // La queue est le premier point du corps
+        let snake = match rng.gen::<bool>() {
+            true => Snake {
+                id,
+                head: Point::new(x + len, y),
+                body: (x..x + len).map(|x| Point::new(x, y)).collect(),
+                direction: Direction::Right,
+            },
+            false => Snake {
+                id,
+                head: Point::new(x + span - 1 - len, y),
+                body: (x + span - len..x + span)
+                    .rev()
+                    .map(|x| Point::new(x, y))
+                    .collect(),
+                direction: Direction::Left,
+            },
+        };
+        let i = id as usize - 1;
+        self.snakes[i] = Some(snake);
+        self.scores[i] = PlayerStatus::Player(0);
+        state.queues[i] = snake::DirectionQueue::default();
+        true
+    }
+
+    // Termine le compte rendu de la partie dans le fichier de log avec
+    // son issue et les scores de chaque serpent
+    fn record_end(&self, outcome: &str, state: &ServerState) {
+        let mut record = format!("Game ended: {}\n", outcome);
+        for (i, status) in self.scores.iter().enumerate() {
+            let status = match status {
+                PlayerStatus::Player(score) => score.to_string(),
+                PlayerStatus::Loser => String::from("lost"),
+                PlayerStatus::Leaver => String::from("left"),
+            };
+            record += &format!("  {}: {}\n", self.name(i as u32 + 1), status);
+        }
+        log_in_file(&state.log_file, record);
+    }
 }
 
-fn log_in_file(s: String) {
-    if let Ok(mut file) = OpenOptions::new().append(true).open(LOG_FILE) {
-        file.write(s.as_bytes()).unwrap();
+// Ajoute _s_ à la fin du fichier de log, sans effacer les parties précédentes
+fn log_in_file(path: &str, s: String) {
+    if let Ok(mut file) = OpenOptions::new().create(true).append(true).open(path) {
+        file.write_all(s.as_bytes()).unwrap();
     }
 }
@@ -1,17 +1,20 @@
-use game::TurnOutcome;
-use shared_structures::*;
-use std::env;
-use std::io::prelude::*;
-use std::net::{Shutdown, TcpListener, TcpStream};
-use std::thread::sleep;
-use std::time::Duration;
+use client::reject_client;
+use game::GameLogic;
+use room::{join_room, Rooms};
+use snake_protocol::*;
+use std::collections::HashMap;
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use udp::UdpChannel;
+pub mod client;
+pub mod config;
 pub mod game;
-pub mod shared_structures;
+pub mod room;
 pub mod snake;
+pub mod udp;
+pub mod web;
 
-// Default number of snakes and bots
-const NB_SNAKES: u32 = 4;
-const NB_BOT: u32 = 3;
 // Une macro pour afficher des messages de log de la forme
 // [LOG] ...
 #[macro_export]
@@ -32,183 +35,139 @@ macro_rules! error {
 }
 
 fn main() {
-    let listener = TcpListener::bind(SERVER_ADDR).unwrap();
-    log!("Server address: {}", SERVER_ADDR);
-    let (nb_snakes, nb_bots) = match get_args() {
-        Ok(v) => v,
+    let config = match config::load() {
+        Ok(config) => config,
         Err(msg) => {
             error!("{}", msg);
             return;
         }
     };
-    let nb_humans = nb_snakes - nb_bots;
-
-    // Store every client connected with the players numbers associated
-    let mut clients: Vec<(Vec<u32>, Option<TcpStream>)> = vec![];
-    let mut players_pending: u32 = 0;
-    log!("Server waiting for connection");
-    while players_pending < nb_humans {
-        let (mut sock, addr) = listener.accept().expect("Connection failed");
-        // On demande un message "Init" aux clients
-        if let ClientMsg::Init(nb_players) = listen_to_client((&mut vec![], Some(&mut sock))) {
-            // Trop de joueurs
-            if players_pending as u32 + nb_players > nb_humans as u32 {
-                log!("Client {} has too many players for this game", addr);
-                if let Ok(_) = send_msg_to_client(
-                    &ServerMsg::Error(format!("Too many players for this game")),
-                    &mut sock,
-                ) {
-                    sock.shutdown(Shutdown::Both).unwrap();
-                }
-            } else {
-                // Attribue les numéros de serpents aux joueurs
-                let players_numbers: Vec<u32> =
-                    (players_pending + 1..players_pending + 1 + nb_players).collect();
-                players_pending = players_pending + nb_players;
-                // Envoie les numéros des serpents aux joueurs
-                if let Ok(_) =
-                    send_msg_to_client(&ServerMsg::InitAck(players_numbers.clone()), &mut sock)
-                {
-                    log!("New connection from {}", addr);
-                    clients.push((players_numbers, Some(sock)));
-                }
-            }
-        } else {
-            log!("Client {} did not send its number of players", addr);
-            sock.shutdown(Shutdown::Both).unwrap();
-        }
+    // Vérifie les paramètres des parties avant d'attendre les joueurs
+    if let Err(msg) = Game::init(&config) {
+        error!("{}", msg);
+        return;
     }
+    let listener = match bind(&config.bind) {
+        Ok(listener) => listener,
+        Err(msg) => {
+            error!("{}", msg);
+            return;
+        }
+    };
+    log!("Server address: {}", config.bind);
+    // Sans UDP les clients reçoivent tout par TCP
+    let udp = match listener
+        .local_addr()
+        .and_then(|addr| UdpChannel::bind(&[addr]))
+    {
+        Ok(udp) => Some(udp),
+        Err(e) => {
+            log!("UDP updates are disabled: {}", e);
+            None
+        }
+    };
 
-    // We restart game after 3 seconds
-    loop {
-        match Game::init(nb_snakes, nb_bots) {
-            Ok(game) => {
-                log!(
-                    "Game created: snakes: {} - human players: {} - bots: {}",
-                    nb_snakes,
-                    nb_humans,
-                    nb_bots
-                );
-
-                play(game, &mut clients)
-            }
+    // Les navigateurs jouent à travers une passerelle WebSocket
+    if let Some(web) = &config.web {
+        let web_listener = match bind(web) {
+            Ok(listener) => listener,
             Err(msg) => {
                 error!("{}", msg);
                 return;
             }
-        }
-
-        // Si il n'y a plus de joueurs "humains" on arrête
-        if clients
-            .iter()
-            .fold(true, |acc, (_, stream)| acc && stream.is_none())
-        {
-            log!("No players left, turning off the server");
-            return;
-        }
-
-        sleep(Duration::from_secs(3));
+        };
+        let server = match listener.local_addr() {
+            Ok(addr) => addr,
+            Err(e) => {
+                error!("Could not get the server address: {}", e);
+                return;
+            }
+        };
+        log!("Web client address: http://{}", web);
+        thread::spawn(move || web::serve(web_listener, server));
     }
-}
 
-// Crée une partie en fonction des arguments fournis
-fn get_args() -> Result<(u32, u32), String> {
-    let args: Vec<String> = env::args().collect();
-    if args.len() > 1 {
-        if let Ok(nb_snakes) = args[1].parse() {
-            if args.len() > 2 {
-                if let Ok(nb_bots) = args[2].parse() {
-                    return Ok((nb_snakes, nb_bots));
-                } else {
-                    return Err(format!("Arguments should be integers: {}", args[2]));
-                }
-            } else {
-                return Ok((nb_snakes, nb_snakes - 1));
+    // Tous les salons partagent les paramètres du serveur
+    let config = Arc::new(config);
+    let rooms: Rooms = Arc::new(Mutex::new(HashMap::new()));
+
+    // Sur Ctrl-C ou SIGTERM, les salons préviennent leurs clients et
+    // terminent le compte rendu de leur partie avant l'arrêt du serveur
+    let stopping = rooms.clone();
+    if let Err(e) = ctrlc::set_handler(move || {
+        log!("Server shutting down");
+        room::shutdown(&stopping, "The server has been stopped");
+        log!("Server stopped");
+        std::process::exit(0);
+    }) {
+        error!("Could not handle termination signals: {}", e);
+        return;
+    }
+    log!("Server waiting for connection");
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                log!("Connection failed: {}", e);
+                continue;
             }
-        } else {
-            return Err(format!("Arguments should be integers: {}", args[1]));
-        }
+        };
+        // Le handshake est lu dans un thread pour qu'un client lent
+        // ne bloque pas les autres connexions
+        let rooms = rooms.clone();
+        let config = config.clone();
+        let udp = udp.clone();
+        thread::spawn(move || handle_connection(stream, &rooms, &config, udp));
     }
-    Ok((NB_SNAKES, NB_BOT))
 }
 
-// Lance une partie de Snake
-// Retourne true pour relancer une partie sinon retoune false
-fn play(mut game: Game, players: &mut Vec<(Vec<u32>, Option<TcpStream>)>) {
-    // TODO we should not clone
-    send_msg_to_clients(ServerMsg::Playing(game.clone(), vec![]), players);
-    loop {
-        sleep(Duration::from_millis(game.speed));
-
-        let players_inputs = listen_to_clients(players);
-
-        // on fait avancer le jeu d'un tour
-        let turn_outcome = game.turn(players_inputs);
-
-        // on check si la partie est finie
-        match turn_outcome {
-            // On envoie la partie avec les perdants éventuels
-            TurnOutcome::Playing(losers) => {
-                send_msg_to_clients(ServerMsg::Playing(game.clone(), losers), players)
-            }
-            TurnOutcome::End(winner) => {
-                send_msg_to_clients(ServerMsg::End(winner), players);
-                log!("Game ended");
-                return;
-            }
-        }
-    }
+fn bind(addr: &str) -> Result<TcpListener, String> {
+    let addrs = resolve_addr(addr)?;
+    TcpListener::bind(&addrs[..]).map_err(|e| format!("Could not listen on {}: {}", addr, e))
 }
 
-// Envoie un message aux différents clients
-fn send_msg_to_clients(msg: ServerMsg, clients: &mut Vec<(Vec<u32>, Option<TcpStream>)>) {
-    // For debugging purpose
-    //log!("Sending message to clients:\n {:?}", msg);
-    for (_, opt_stream) in clients.iter_mut() {
-        if let Some(stream) = opt_stream {
-            match send_msg_to_client(&msg, stream) {
-                Ok(_) => stream.flush().unwrap(),
-                Err(e) => match e.kind() {
-                    std::io::ErrorKind::BrokenPipe => {
-                        *opt_stream = None;
-                    }
-                    _ => panic!("No sé que pasa"),
-                },
-            };
+// On demande un message "Init" au client puis on l'envoie dans son salon
+fn handle_connection(
+    mut stream: TcpStream,
+    rooms: &Rooms,
+    config: &Arc<config::Config>,
+    udp: Option<Arc<UdpChannel>>,
+) {
+    let addr = match stream.peer_addr() {
+        Ok(addr) => addr,
+        Err(e) => {
+            log!("Connection failed: {}", e);
+            return;
+        }
+    };
+    match listen_to_handshake(&mut stream) {
+        Ok(handshake) => join_room(stream, addr, handshake, rooms, config, udp),
+        Err(msg) => {
+            log!("Client {} refused: {}", addr, msg);
+            reject_client(&mut stream, msg);
         }
     }
 }
 
-fn send_msg_to_client(msg: &ServerMsg, client: &mut TcpStream) -> Result<usize, std::io::Error> {
-    let json = serde_json::to_string(msg).unwrap();
-    client.write(json.as_bytes())
-}
-
-fn listen_to_clients(clients: &mut Vec<(Vec<u32>, Option<TcpStream>)>) -> Vec<ClientMsg> {
-    let mut v = vec![];
-    for (players_nb, stream) in clients.iter_mut() {
-        let json = listen_to_client((players_nb, stream.as_mut()));
-        v.push(json);
+// Lit le message "Init" d'un client et vérifie qu'il utilise
+// la même version du protocole que le serveur
+fn listen_to_handshake(stream: &mut TcpStream) -> Result<Handshake, String> {
+    let frame = read_frame(stream).map_err(|e| format!("Could not read handshake: {}", e))?;
+    match serde_json::from_slice(&frame) {
+        Ok(ClientMsg::Init(handshake)) if handshake.version == PROTOCOL_VERSION => Ok(handshake),
+        Ok(ClientMsg::Init(handshake)) => Err(version_mismatch(handshake.version)),
+        Ok(_) => Err("The first message should be \"Init\"".to_string()),
+        Err(e) => match handshake_version(&frame) {
+            Some(version) => Err(version_mismatch(version)),
+            None => Err(format!("Invalid \"Init\" message: {}", e)),
+        },
     }
-    v
 }
 
-fn listen_to_client(client: (&mut Vec<u32>, Option<&mut TcpStream>)) -> ClientMsg {
-    let mut buffer = [0; 1024];
-    let stream = client.1;
-    if let Some(stream) = stream {
-        stream.read(&mut buffer).unwrap();
-        let json = String::from_utf8_lossy(&buffer);
-        let json = &json.trim_end_matches(char::from(0));
-        if let Ok(json) = serde_json::from_str(&json) {
-            return json;
-        } else {
-            if let Ok(addr) = stream.peer_addr() {
-                error!("Client {} has sent erronous data:\n{}", addr, json);
-            } else {
-                error!("Client has sent erronous data:\n{}", json);
-            }
-        }
-    }
-    ClientMsg::Leave(client.0.len() as u32)
+fn version_mismatch(client_version: u32) -> String {
+    format!(
+        "Protocol version mismatch: client uses version {} but server uses version {}, \
+         please use a compatible snake_client",
+        client_version, PROTOCOL_VERSION
+    )
 }
@@ -0,0 +1,974 @@
+use crate::client::{
+    merge_input, new_session, reject_client, send_msg_to_client, send_msg_to_clients, Client,
+    PING_PERIOD,
+};
+use crate::config::Config;
+use crate::game::{ClientInput, GameLogic, ServerState, TurnOutcome};
+use crate::log;
+use crate::udp::{UdpChannel, UdpLink};
+use snake_protocol::*;
+use std::collections::HashMap;
+use std::net::{SocketAddr, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Longueur maximale du nom d'un salon
+pub const MAX_ROOM_NAME_LEN: usize = 32;
+// Nombre de tours entre deux envois de la partie entière, pour que les
+// clients qui reçoivent des deltas se resynchronisent
+const KEYFRAME_PERIOD: u64 = 50;
+// Temps laissé aux salons pour prévenir leurs clients à l'arrêt du serveur
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Plus aucun salon ne s'ouvre une fois l'arrêt du serveur commencé
+static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+
+// Salons ouverts sur le serveur, indexés par leur nom.
+// Chaque salon est joignable par le canal de son thread
+pub type Rooms = Arc<Mutex<HashMap<String, Sender<RoomEvent>>>>;
+
+// Évènements traités par le thread d'un salon
+pub enum RoomEvent {
+    // Un client a terminé le handshake et demande à entrer dans le salon
+    Join(TcpStream, SocketAddr, Handshake),
+    // Message d'un client du salon, avec l'index du client
+    Msg(usize, ClientMsg),
+    // La connexion d'un client a été coupée, avec le numéro de cette connexion
+    Lost(usize, u32),
+    // Commandes d'un client reçues par UDP, avec l'adresse d'envoi
+    Datagram(usize, SocketAddr, Vec<ClientMsg>),
+    // Le serveur s'arrête, avec la raison donnée aux clients
+    Shutdown(String),
+}
+
+// Un salon a sa propre partie, ses propres joueurs et son propre rythme
+pub struct Room {
+    name: String,
+    config: Arc<Config>,
+    rooms: Rooms,
+    // Clients du salon avec les numéros des serpents qu'ils contrôlent
+    clients: Vec<Client>,
+    // Donné aux clients pour qu'ils transmettent leurs messages au salon
+    sender: Sender<RoomEvent>,
+    events: Receiver<RoomEvent>,
+    // Serpents contrôlés par des bots, y compris les places restées libres
+    // quand l'hôte lance la partie sans attendre
+    bots: Vec<u32>,
+    // Places reprises par des bots après le départ de leur joueur
+    replaced: Vec<u32>,
+    // None si le serveur n'a pas pu ouvrir son port UDP
+    udp: Option<Arc<UdpChannel>>,
+    // Numéro du dernier tour joué dans le salon, toutes parties confondues
+    tick: u64,
+    // Places données pendant la partie en cours, à ajouter au jeu
+    seated: Vec<u32>,
+    // Raison de l'arrêt du serveur, la partie en cours est interrompue
+    shutdown: Option<String>,
+}
+
+// Envoie un client dans le salon qu'il a demandé, en ouvrant le salon si besoin.
+// Le salon par défaut existe toujours et n'a pas besoin d'être créé
+pub fn join_room(
+    mut stream: TcpStream,
+    addr: SocketAddr,
+    handshake: Handshake,
+    rooms: &Rooms,
+    config: &Arc<Config>,
+    udp: Option<Arc<UdpChannel>>,
+) {
+    let name = handshake.room.name().to_string();
+    if let Err(msg) = check_room_name(&name) {
+        log!("Client {} refused: {}", addr, msg);
+        reject_client(&mut stream, msg);
+        return;
+    }
+    // Le verrou est gardé jusqu'à l'envoi pour qu'un salon ne puisse pas
+    // se fermer entre sa recherche et l'arrivée du client
+    let mut open_rooms = rooms.lock().unwrap();
+    if SHUTTING_DOWN.load(Ordering::SeqCst) {
+        log!("Client {} refused: the server is shutting down", addr);
+        reject_client(&mut stream, String::from("The server is shutting down"));
+        return;
+    }
+    let sender = match (&handshake.room, open_rooms.get(&name)) {
+        (_, None) if handshake.session.is_some() => {
+            let msg = format!("Room \"{}\" has been closed", name);
+            log!("Client {} could not reconnect: {}", addr, msg);
+            reject_client(&mut stream, msg);
+            return;
+        }
+        // Un salon sans joueur serait fermé aussitôt
+        (_, None) if handshake.role == Role::Spectator => {
+            let msg = format!("No room named \"{}\" to watch", name);
+            log!("Client {} refused: {}", addr, msg);
+            reject_client(&mut stream, msg);
+            return;
+        }
+        (RoomChoice::Create(_), Some(_)) => {
+            let msg = format!("Room \"{}\" already exists", name);
+            log!("Client {} refused: {}", addr, msg);
+            reject_client(&mut stream, msg);
+            return;
+        }
+        (RoomChoice::Join(_), None) if name != DEFAULT_ROOM => {
+            let msg = format!("No room named \"{}\"", name);
+            log!("Client {} refused: {}", addr, msg);
+            reject_client(&mut stream, msg);
+            return;
+        }
+        (_, Some(sender)) => sender.clone(),
+        (_, None) => {
+            let sender = Room::open(name.clone(), config.clone(), rooms.clone(), udp);
+            open_rooms.insert(name, sender.clone());
+            sender
+        }
+    };
+    if let Err(e) = sender.send(RoomEvent::Join(stream, addr, handshake)) {
+        if let RoomEvent::Join(mut stream, _, _) = e.0 {
+            reject_client(&mut stream, "This room has been closed".to_string());
+        }
+    }
+}
+
+// Prévient tous les salons de l'arrêt du serveur et attend qu'ils aient
+// terminé leur partie et fermé les connexions de leurs clients
+pub fn shutdown(rooms: &Rooms, reason: &str) {
+    {
+        let open_rooms = rooms.lock().unwrap();
+        SHUTTING_DOWN.store(true, Ordering::SeqCst);
+        for sender in open_rooms.values() {
+            let _ = sender.send(RoomEvent::Shutdown(reason.to_string()));
+        }
+    }
+    let deadline = Instant::now() + SHUTDOWN_TIMEOUT;
+    while !rooms.lock().unwrap().is_empty() {
+        if Instant::now() >= deadline {
+            log!("Some rooms did not close in time");
+            return;
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+fn check_room_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name.len() > MAX_ROOM_NAME_LEN {
+        return Err(format!(
+            "The name of a room must have between 1 and {} characters",
+            MAX_ROOM_NAME_LEN
+        ));
+    }
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(String::from(
+            "The name of a room can only contain letters, digits, '-' and '_'",
+        ));
+    }
+    Ok(())
+}
+
+impl Room {
+    // Lance le thread du salon et retourne le canal pour lui envoyer des clients
+    fn open(
+        name: String,
+        config: Arc<Config>,
+        rooms: Rooms,
+        udp: Option<Arc<UdpChannel>>,
+    ) -> Sender<RoomEvent> {
+        let (sender, events) = channel();
+        let room = Room {
+            name,
+            config,
+            rooms,
+            clients: vec![],
+            sender: sender.clone(),
+            events,
+            bots: vec![],
+            replaced: vec![],
+            udp,
+            tick: 0,
+            seated: vec![],
+            shutdown: None,
+        };
+        thread::spawn(move || room.run());
+        sender
+    }
+
+    fn run(mut self) {
+        log!("Room \"{}\" opened, waiting for players", self.name);
+        if self.lobby() {
+            // We restart game after 3 seconds
+            loop {
+                // L'arrêt a pu être demandé pendant la pause entre deux parties
+                if self.shutting_down() {
+                    break;
+                }
+                self.seat_waiting_clients();
+                match Game::init(&self.config) {
+                    Ok(mut game) => {
+                        game.bots = self.bots.clone();
+                        game.names = self.names(game.nb_snakes);
+                        log!(
+                            "Room \"{}\": game created: snakes: {} - human players: {} - bots: {}",
+                            self.name,
+                            game.nb_snakes,
+                            game.nb_snakes - game.bots.len() as u32,
+                            game.bots.len()
+                        );
+                        let state = ServerState::new(
+                            game.nb_snakes,
+                            self.config.leaver_bot.clone(),
+                            self.replaced.clone(),
+                            &self.name,
+                        );
+                        self.play(game, state)
+                    }
+                    Err(msg) => {
+                        log!("Room \"{}\": could not create the game: {}", self.name, msg);
+                        break;
+                    }
+                }
+
+                // Si il n'y a plus de joueurs "humains" on ferme le salon
+                self.collect_inputs(Instant::now());
+                if self.is_empty() || self.shutting_down() {
+                    break;
+                }
+
+                thread::sleep(Duration::from_secs(3));
+            }
+        }
+        self.close();
+    }
+
+    // Salle d'attente: les clients voient qui est présent et se déclarent
+    // prêts. La partie commence quand toutes les places sont prises et que
+    // tous sont prêts, ou plus tôt si l'hôte le demande.
+    // Retourne false si tous les clients sont partis avant le début de la partie
+    fn lobby(&mut self) -> bool {
+        loop {
+            let event = match self.events.recv_timeout(PING_PERIOD) {
+                Ok(event) => Some(event),
+                Err(RecvTimeoutError::Timeout) => None,
+                // Le salon garde un émetteur, le canal ne peut pas être fermé
+                Err(RecvTimeoutError::Disconnected) => panic!("Room channel closed"),
+            };
+            let timed_out = self.heartbeat();
+            match event {
+                // Rien n'a changé, pas besoin de renvoyer le salon
+                None if !timed_out => continue,
+                None => (),
+                Some(RoomEvent::Join(stream, addr, handshake)) if handshake.session.is_some() => {
+                    self.resume_client(stream, addr, handshake)
+                }
+                Some(RoomEvent::Join(stream, addr, handshake)) => {
+                    self.add_client(stream, addr, handshake, false)
+                }
+                // Il n'y a rien à reprendre avant le début de la partie,
+                // les places du client sont libérées
+                Some(RoomEvent::Lost(id, connection)) => {
+                    self.clients[id].connection_lost(connection)
+                }
+                Some(RoomEvent::Shutdown(reason)) => {
+                    self.shutdown = Some(reason);
+                    return false;
+                }
+                // Le client fait connaître son adresse UDP, ses commandes
+                // n'ont pas d'effet avant la partie
+                Some(RoomEvent::Datagram(id, addr, _)) => {
+                    self.clients[id].datagram_from(addr);
+                    continue;
+                }
+                Some(RoomEvent::Msg(id, msg)) => match msg {
+                    ClientMsg::Ready(ready) => self.clients[id].ready = ready,
+                    ClientMsg::Start if self.host() == Some(id) => {
+                        log!("Room \"{}\": the host starts the game", self.name);
+                        break;
+                    }
+                    ClientMsg::Start => {
+                        log!("Room \"{}\": only the host can start the game", self.name);
+                    }
+                    ClientMsg::Leave(_) => self.clients[id].disconnect(),
+                    ClientMsg::Pong(ping) => self.clients[id].pong(ping),
+                    // Le salon n'a pas changé
+                    ClientMsg::Chat(text) => {
+                        self.chat(id, text);
+                        continue;
+                    }
+                    ClientMsg::SnakeDirection(..) | ClientMsg::Init(_) => (),
+                },
+            }
+            self.free_leavers_seats();
+            if self.is_empty() {
+                return false;
+            }
+            if self.free_seats().is_empty() && self.players().all(|client| client.ready) {
+                log!("Room \"{}\": all players are ready", self.name);
+                break;
+            }
+            self.send_lobby();
+        }
+        // Les places libres sont données à des bots
+        self.free_leavers_seats();
+        let humans: Vec<u32> = self.players().flat_map(|c| c.players.clone()).collect();
+        self.bots = (1..=self.config.nb_snakes)
+            .filter(|snake| !humans.contains(snake))
+            .collect();
+        true
+    }
+
+    // Pendant une partie, _playing_, un joueur prend une place libre tout de
+    // suite, ou attend la partie suivante quand il n'y en a pas assez
+    fn add_client(
+        &mut self,
+        mut stream: TcpStream,
+        addr: SocketAddr,
+        handshake: Handshake,
+        playing: bool,
+    ) {
+        let nb_players = match handshake.role {
+            Role::Player => handshake.nb_players as usize,
+            Role::Spectator => 0,
+        };
+
+        if handshake.role == Role::Player && nb_players == 0 {
+            log!("Client {} has no players", addr);
+            reject_client(&mut stream, "At least 1 player is needed".to_string());
+            return;
+        }
+
+        let free_seats = match playing {
+            true => self.open_seats(),
+            false => self.free_seats(),
+        };
+        let waiting = playing && nb_players > free_seats.len();
+        // Trop de joueurs
+        if nb_players > free_seats.len() && !waiting {
+            log!(
+                "Client {} has too many players for room \"{}\"",
+                addr,
+                self.name
+            );
+            reject_client(&mut stream, "Too many players for this room".to_string());
+            return;
+        }
+
+        if let Err(msg) = self.check_names(&handshake, nb_players) {
+            log!("Client {} refused: {}", addr, msg);
+            reject_client(&mut stream, msg);
+            return;
+        }
+        // Attribue aux joueurs les plus petits numéros de serpents libres
+        let players_numbers: Vec<u32> = match waiting {
+            true => vec![],
+            false => free_seats[..nb_players].to_vec(),
+        };
+        let token = new_session();
+        let id = self.clients.len();
+        let capabilities = self.negotiate(&handshake);
+        let udp = self.udp_link(id, &capabilities);
+        let ack = HandshakeAck {
+            version: PROTOCOL_VERSION,
+            snakes: players_numbers.clone(),
+            capabilities: capabilities.clone(),
+            session: self.session(&token, !players_numbers.is_empty()),
+            udp_key: udp.as_ref().map(|udp| udp.key),
+        };
+        // Envoie les numéros des serpents aux joueurs
+        if send_msg_to_client(&ServerMsg::InitAck(ack), &mut stream, Encoding::Json).is_ok() {
+            let mut client = Client::new(
+                id,
+                players_numbers.clone(),
+                token,
+                capabilities,
+                udp,
+                stream,
+                self.sender.clone(),
+            );
+            client.names = player_names(&handshake, &players_numbers);
+            if waiting {
+                client.waiting = Some(handshake);
+            }
+            log!(
+                "Room \"{}\": {} joined from {}",
+                self.name,
+                client.name(),
+                addr
+            );
+            self.clients.push(client);
+            // Un joueur arrivé pendant la partie y entre au prochain tour
+            if playing {
+                self.release_seats(&players_numbers);
+                self.seated.extend(players_numbers);
+            }
+        }
+    }
+
+    // Un joueur revient avec son jeton de session et reprend ses serpents
+    fn resume_client(&mut self, mut stream: TcpStream, addr: SocketAddr, handshake: Handshake) {
+        let grace = self.grace();
+        let id = self.clients.iter().position(|client| {
+            client.is_player()
+                && Some(&client.session) == handshake.session.as_ref()
+                && (client.stream.is_some() || client.lost_at.is_some())
+                && !client.session_expired(grace)
+        });
+        let id = match id {
+            Some(id) => id,
+            None => {
+                log!("Client {} could not reconnect: unknown session", addr);
+                reject_client(&mut stream, "Your session has expired".to_string());
+                return;
+            }
+        };
+        let capabilities = self.negotiate(&handshake);
+        let udp = self.udp_link(id, &capabilities);
+        let ack = HandshakeAck {
+            version: PROTOCOL_VERSION,
+            snakes: self.clients[id].players.clone(),
+            capabilities: capabilities.clone(),
+            session: self.session(&self.clients[id].session, true),
+            udp_key: udp.as_ref().map(|udp| udp.key),
+        };
+        if send_msg_to_client(&ServerMsg::InitAck(ack), &mut stream, Encoding::Json).is_ok() {
+            log!(
+                "Room \"{}\": {} is back from {}",
+                self.name,
+                self.clients[id].name(),
+                addr
+            );
+            self.clients[id].reconnect(stream, capabilities, udp);
+        }
+    }
+
+    // Fonctionnalités demandées par le client que le salon peut utiliser
+    fn negotiate(&self, handshake: &Handshake) -> Vec<Capability> {
+        let mut capabilities = handshake.negotiate(SUPPORTED_CAPABILITIES);
+        if self.udp.is_none() {
+            capabilities.retain(|c| *c != Capability::UdpUpdates);
+        }
+        capabilities
+    }
+
+    // Clé UDP d'un client qui a choisi ce canal
+    fn udp_link(&self, id: usize, capabilities: &[Capability]) -> Option<UdpLink> {
+        match &self.udp {
+            Some(udp) if capabilities.contains(&Capability::UdpUpdates) => Some(UdpChannel::link(
+                udp,
+                self.sender.clone(),
+                id,
+                Encoding::negotiated(capabilities),
+            )),
+            _ => None,
+        }
+    }
+
+    // Session annoncée à un joueur, si le serveur accepte les reconnexions
+    fn session(&self, token: &str, is_player: bool) -> Option<Session> {
+        match is_player && self.config.reconnect_grace > 0 {
+            true => Some(Session {
+                token: token.to_string(),
+                grace: self.config.reconnect_grace,
+            }),
+            false => None,
+        }
+    }
+
+    fn grace(&self) -> Duration {
+        Duration::from_secs(self.config.reconnect_grace)
+    }
+
+    // Les joueurs qui ne sont pas revenus à temps quittent la partie
+    fn expire_sessions(&mut self) {
+        let grace = self.grace();
+        for client in self.clients.iter_mut() {
+            if client.session_expired(grace) {
+                log!(
+                    "Room \"{}\": {} did not come back",
+                    self.name,
+                    client.name()
+                );
+                client.disconnect();
+            }
+        }
+    }
+
+    // Pings des clients, ceux qui ne répondent plus sont déconnectés et
+    // quittent la partie. Retourne true si un client a été déconnecté
+    fn heartbeat(&mut self) -> bool {
+        let idle_timeout =
+            (self.config.idle_timeout > 0).then(|| Duration::from_secs(self.config.idle_timeout));
+        let mut timed_out = false;
+        for client in self.clients.iter_mut() {
+            timed_out |= client.heartbeat(idle_timeout);
+        }
+        timed_out
+    }
+
+    // Latence en millisecondes des joueurs connectés, pour chaque serpent
+    fn latencies(&self, nb_snakes: u32) -> Vec<Option<u32>> {
+        let mut latencies = vec![None; nb_snakes as usize];
+        for client in self.players() {
+            for snake in client.players.iter() {
+                if let Some(latency) = latencies.get_mut(*snake as usize - 1) {
+                    *latency = client.latency.map(|l| l.as_millis() as u32);
+                }
+            }
+        }
+        latencies
+    }
+
+    // Nom de chaque serpent, les bots et les places libres gardent leur nom par défaut
+    fn names(&self, nb_snakes: u32) -> Vec<String> {
+        let mut names: Vec<String> = (1..=nb_snakes).map(default_name).collect();
+        for client in self.clients.iter() {
+            for (snake, name) in client.players.iter().zip(client.names.iter()) {
+                if let Some(default) = names.get_mut(*snake as usize - 1) {
+                    *default = name.clone();
+                }
+            }
+        }
+        names
+    }
+
+    // Vérifie les surnoms d'un nouveau client. Deux joueurs du salon, y compris
+    // ceux qui attendent une place, ne peuvent pas avoir le même surnom
+    fn check_names(&self, handshake: &Handshake, nb_players: usize) -> Result<(), String> {
+        if handshake.names.len() > nb_players {
+            return Err(String::from("More nicknames than players"));
+        }
+        let taken: Vec<String> = self
+            .clients
+            .iter()
+            .filter(|client| client.stream.is_some() || client.lost_at.is_some())
+            .flat_map(|client| match &client.waiting {
+                Some(handshake) => handshake.names.clone(),
+                None => client.names.clone(),
+            })
+            .collect();
+        check_new_names(&handshake.names, &taken)
+    }
+
+    // Places qu'un joueur peut prendre pendant une partie: celles des bots et
+    // celles des joueurs partis sans pouvoir revenir
+    fn open_seats(&self) -> Vec<u32> {
+        let taken: Vec<u32> = self
+            .clients
+            .iter()
+            .filter(|client| client.stream.is_some() || client.lost_at.is_some())
+            .flat_map(|client| client.players.clone())
+            .collect();
+        (1..=self.config.nb_snakes)
+            .filter(|snake| !taken.contains(snake))
+            .collect()
+    }
+
+    // Retire ces places aux bots et aux joueurs partis
+    fn release_seats(&mut self, seats: &[u32]) {
+        self.bots.retain(|bot| !seats.contains(bot));
+        self.replaced.retain(|bot| !seats.contains(bot));
+        for client in self.clients.iter_mut() {
+            if client.stream.is_some() || client.lost_at.is_some() {
+                continue;
+            }
+            while let Some(i) = client.players.iter().position(|p| seats.contains(p)) {
+                client.players.remove(i);
+                if i < client.names.len() {
+                    client.names.remove(i);
+                }
+            }
+        }
+    }
+
+    // Donne leurs places aux joueurs arrivés pendant la partie précédente,
+    // dans l'ordre d'arrivée, tant qu'il en reste assez
+    fn seat_waiting_clients(&mut self) {
+        for id in 0..self.clients.len() {
+            let handshake = match &self.clients[id].waiting {
+                Some(handshake) if self.clients[id].stream.is_some() => handshake.clone(),
+                _ => continue,
+            };
+            let seats = self.open_seats();
+            let nb_players = handshake.nb_players as usize;
+            if nb_players > seats.len() {
+                continue;
+            }
+            let seats = seats[..nb_players].to_vec();
+            self.release_seats(&seats);
+            let session = self.session(&self.clients[id].session, true);
+            let client = &mut self.clients[id];
+            client.players = seats.clone();
+            client.names = player_names(&handshake, &seats);
+            client.waiting = None;
+            log!(
+                "Room \"{}\": {} takes seats {:?}",
+                self.name,
+                client.name(),
+                seats
+            );
+            client.send(&ServerMsg::Seats(seats, session));
+        }
+    }
+
+    // Ajoute au jeu les joueurs arrivés pendant la partie: ils prennent le
+    // serpent d'un bot encore en vie, ou apparaissent dans un espace libre
+    fn seat_in_game(&mut self, game: &mut Game, state: &mut ServerState) {
+        for snake in std::mem::take(&mut self.seated) {
+            game.bots.retain(|bot| *bot != snake);
+            state.replaced.retain(|bot| *bot != snake);
+            if game.snakes[snake as usize - 1].is_none() && !game.add_snake(snake, state) {
+                log!(
+                    "Room \"{}\": no free space for snake {}, it plays the next game",
+                    self.name,
+                    snake
+                );
+            }
+        }
+        game.names = self.names(game.nb_snakes);
+    }
+
+    // Serpents dont le joueur a perdu la connexion et peut encore revenir
+    fn frozen_snakes(&self) -> Vec<u32> {
+        self.clients
+            .iter()
+            .filter(|client| client.lost_at.is_some())
+            .flat_map(|client| client.players.clone())
+            .collect()
+    }
+
+    // Numéros des serpents humains qui n'ont pas encore de joueur
+    fn free_seats(&self) -> Vec<u32> {
+        let taken: Vec<u32> = self.players().flat_map(|c| c.players.clone()).collect();
+        (1..=self.config.nb_humans())
+            .filter(|snake| !taken.contains(snake))
+            .collect()
+    }
+
+    // Un client parti avant le début de la partie libère ses places
+    fn free_leavers_seats(&mut self) {
+        for client in self.clients.iter_mut() {
+            if client.stream.is_none() {
+                client.players.clear();
+                client.names.clear();
+                client.lost_at = None;
+            }
+        }
+    }
+
+    // Clients encore connectés qui contrôlent des serpents
+    fn players(&self) -> impl Iterator<Item = &Client> {
+        self.clients
+            .iter()
+            .filter(|client| client.stream.is_some() && client.is_player())
+    }
+
+    // L'hôte est le plus ancien joueur encore connecté
+    fn host(&self) -> Option<usize> {
+        self.clients
+            .iter()
+            .position(|client| client.stream.is_some() && client.is_player())
+    }
+
+    fn send_lobby(&mut self) {
+        let host = self.host();
+        let players = self
+            .clients
+            .iter()
+            .enumerate()
+            .filter(|(_, client)| client.stream.is_some() && client.is_player())
+            .map(|(id, client)| LobbyPlayer {
+                snakes: client.players.clone(),
+                names: client.names.clone(),
+                ready: client.ready,
+                host: host == Some(id),
+            })
+            .collect();
+        let lobby = Lobby {
+            room: self.name.clone(),
+            seats: self.config.nb_humans(),
+            players,
+            spectators: self
+                .clients
+                .iter()
+                .filter(|client| client.stream.is_some() && !client.is_player())
+                .count() as u32,
+        };
+        send_msg_to_clients(ServerMsg::Lobby(lobby), &mut self.clients);
+    }
+
+    // Relaie un message du chat à tous les clients du salon, y compris son
+    // auteur. Les messages d'un client qui écrit trop vite sont ignorés
+    fn chat(&mut self, id: usize, text: String) {
+        let text = match chat_text(&text) {
+            Some(text) => text,
+            None => return,
+        };
+        if !self.clients[id].allow_chat() {
+            log!(
+                "Room \"{}\": ignoring chat message of {}, sent too fast",
+                self.name,
+                self.clients[id].name()
+            );
+            return;
+        }
+        let msg = ChatMessage {
+            snakes: self.clients[id].players.clone(),
+            author: match self.clients[id].is_player() {
+                true => self.clients[id].names.join(", "),
+                false => String::from("Spectator"),
+            },
+            text,
+        };
+        send_msg_to_clients(ServerMsg::Chat(msg), &mut self.clients);
+    }
+
+    // Lance une partie de Snake
+    fn play(&mut self, mut game: Game, mut state: ServerState) {
+        // Les joueurs arrivés à la fin de la partie précédente sont déjà dans celle-ci
+        self.seated.clear();
+        game.frozen = self.frozen_snakes();
+        game.latencies = self.latencies(game.nb_snakes);
+        self.tick += 1;
+        self.send_game(&game, None, vec![]);
+        // État envoyé au tour précédent, base des deltas
+        let mut previous = game.clone();
+        let speed = Duration::from_millis(game.speed);
+        let mut deadline = Instant::now() + speed;
+        loop {
+            // Les commandes arrivées avant la fin du tour sont appliquées
+            let players_inputs = self.collect_inputs(deadline);
+            deadline += speed;
+            // Les bots qui ont repris les serpents des joueurs ne jouent pas seuls
+            if self.is_empty() && state.leaver_bot.is_some() {
+                log!("Room \"{}\": game stopped, no players left", self.name);
+                game.record_end("stopped, no players left", &state);
+                return;
+            }
+            if let Some(reason) = &self.shutdown {
+                log!("Room \"{}\": game interrupted: {}", self.name, reason);
+                game.record_end(&format!("interrupted: {}", reason), &state);
+                return;
+            }
+            if !self.seated.is_empty() {
+                self.seat_in_game(&mut game, &mut state);
+            }
+            game.frozen = self.frozen_snakes();
+            game.latencies = self.latencies(game.nb_snakes);
+
+            // on fait avancer le jeu d'un tour
+            let turn_outcome = game.turn(players_inputs, &mut state);
+
+            // on check si la partie est finie
+            match turn_outcome {
+                // On envoie la partie avec les perdants éventuels
+                TurnOutcome::Playing(losers) => {
+                    // Les places reprises par des bots le restent dans les
+                    // parties suivantes, jusqu'à l'arrivée d'un joueur
+                    for bot in state.replaced.iter() {
+                        if !self.replaced.contains(bot) {
+                            self.bots.push(*bot);
+                            self.replaced.push(*bot);
+                        }
+                    }
+                    self.tick += 1;
+                    let base = (!self.tick.is_multiple_of(KEYFRAME_PERIOD)).then_some(&previous);
+                    self.send_game(&game, base, losers);
+                    previous.clone_from(&game);
+                }
+                TurnOutcome::End(winner) => {
+                    let winner = winner.map(|snake| Winner {
+                        snake,
+                        name: game.name(snake),
+                    });
+                    match &winner {
+                        Some(winner) => {
+                            log!("Room \"{}\": game ended, {} wins", self.name, winner.name);
+                            game.record_end(&format!("{} wins", winner.name), &state);
+                        }
+                        None => {
+                            log!("Room \"{}\": game ended in a draw", self.name);
+                            game.record_end("draw", &state);
+                        }
+                    }
+                    send_msg_to_clients(ServerMsg::End(winner), &mut self.clients);
+                    return;
+                }
+            }
+        }
+    }
+
+    // Envoie l'état de la partie, sous forme de delta aux clients qui le
+    // supportent et ont déjà l'état précédent, en entier aux autres.
+    // Les datagrammes UDP peuvent se perdre, ils contiennent toujours la partie entière
+    fn send_game(&mut self, game: &Game, previous: Option<&Game>, losers: Vec<u32>) {
+        let tick = Tick {
+            id: self.tick,
+            last_input: 0,
+        };
+        let mut delta = previous
+            .and_then(|previous| GameDelta::diff(previous, game))
+            .map(|delta| ServerMsg::Delta(delta, losers.clone(), tick));
+        let mut keyframe = None;
+        for client in self.clients.iter_mut() {
+            match &mut delta {
+                Some(delta)
+                    if client.synced
+                        && client.uses(Capability::DeltaUpdates)
+                        && !client.uses_udp() =>
+                {
+                    acknowledge_input(delta, client);
+                    client.send(delta);
+                }
+                _ => {
+                    let keyframe = keyframe.get_or_insert_with(|| {
+                        ServerMsg::Playing(game.clone(), losers.clone(), tick)
+                    });
+                    acknowledge_input(keyframe, client);
+                    client.synced = client.send_state(keyframe);
+                }
+            }
+        }
+    }
+
+    // Récupère les commandes reçues avant la fin du tour.
+    // Un client qui n'a rien envoyé garde la direction de ses serpents
+    fn collect_inputs(&mut self, deadline: Instant) -> Vec<ClientInput> {
+        self.expire_sessions();
+        self.heartbeat();
+        let mut inputs: Vec<ClientMsg> = self.clients.iter().map(Client::default_input).collect();
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            match self.events.recv_timeout(deadline - now) {
+                Ok(RoomEvent::Msg(id, ClientMsg::Chat(text))) => self.chat(id, text),
+                Ok(RoomEvent::Msg(id, msg)) => {
+                    merge_input(&mut inputs[id], msg, &mut self.clients[id])
+                }
+                // Un datagramme répète les commandes pas encore appliquées,
+                // celles déjà reçues sont ignorées
+                Ok(RoomEvent::Datagram(id, addr, msgs)) => {
+                    self.clients[id].datagram_from(addr);
+                    for msg in msgs {
+                        match msg {
+                            ClientMsg::SnakeDirection(sequence, _)
+                                if sequence > self.clients[id].last_input =>
+                            {
+                                merge_input(&mut inputs[id], msg, &mut self.clients[id])
+                            }
+                            _ => (),
+                        }
+                    }
+                }
+                Ok(RoomEvent::Lost(id, connection)) => {
+                    self.clients[id].connection_lost(connection);
+                    if self.clients[id].lost_at.is_some() {
+                        log!(
+                            "Room \"{}\": waiting {}s for {} to reconnect",
+                            self.name,
+                            self.config.reconnect_grace,
+                            self.clients[id].name()
+                        );
+                    }
+                }
+                Ok(RoomEvent::Join(stream, addr, handshake)) if handshake.session.is_some() => {
+                    self.resume_client(stream, addr, handshake)
+                }
+                // Les spectateurs peuvent arriver pendant la partie, les
+                // joueurs aussi s'il reste des places, sinon ils attendent
+                // la partie suivante
+                Ok(RoomEvent::Join(stream, addr, handshake)) => {
+                    self.add_client(stream, addr, handshake, true);
+                    inputs.extend(
+                        self.clients[inputs.len()..]
+                            .iter()
+                            .map(Client::default_input),
+                    );
+                }
+                Ok(RoomEvent::Shutdown(reason)) => {
+                    self.shutdown = Some(reason);
+                    break;
+                }
+                Err(_) => break,
+            }
+        }
+        self.clients
+            .iter()
+            .map(|client| client.players.clone())
+            .zip(inputs)
+            .collect()
+    }
+
+    // Les spectateurs seuls ne font pas vivre un salon, un joueur qui peut
+    // encore revenir le garde ouvert
+    fn is_empty(&self) -> bool {
+        !self.clients.iter().any(|client| {
+            client.is_player() && (client.stream.is_some() || client.lost_at.is_some())
+        })
+    }
+
+    fn shutting_down(&self) -> bool {
+        self.shutdown.is_some() || SHUTTING_DOWN.load(Ordering::SeqCst)
+    }
+
+    // Retire le salon de la liste, les clients arrivés entre temps sont refusés.
+    // À l'arrêt du serveur aucun client n'arrive plus, le salon reste dans la
+    // liste jusqu'à ce que ses clients soient prévenus
+    fn close(mut self) {
+        if !self.shutting_down() {
+            self.rooms.lock().unwrap().remove(&self.name);
+        }
+        while let Ok(event) = self.events.try_recv() {
+            match event {
+                RoomEvent::Join(mut stream, _, _) => {
+                    reject_client(&mut stream, "This room has been closed".to_string());
+                }
+                RoomEvent::Shutdown(reason) => self.shutdown = Some(reason),
+                _ => (),
+            }
+        }
+        match self.shutdown.take() {
+            Some(reason) => {
+                send_msg_to_clients(ServerMsg::Shutdown(reason.clone()), &mut self.clients);
+                log!("Room \"{}\" closed: {}", self.name, reason);
+            }
+            None => {
+                send_msg_to_clients(
+                    ServerMsg::Error(String::from("The room has been closed: no players left")),
+                    &mut self.clients,
+                );
+                log!("Room \"{}\" closed: no players left", self.name);
+            }
+        }
+        self.rooms.lock().unwrap().remove(&self.name);
+    }
+}
+
+// Surnoms des joueurs d'un client, ceux qui n'en ont pas choisi portent le
+// nom de leur serpent
+fn player_names(handshake: &Handshake, snakes: &[u32]) -> Vec<String> {
+    snakes
+        .iter()
+        .enumerate()
+        .map(|(i, snake)| {
+            handshake
+                .names
+                .get(i)
+                .cloned()
+                .unwrap_or_else(|| default_name(*snake))
+        })
+        .collect()
+}
+
+// Le message est partagé par les clients, seule la dernière commande
+// appliquée change de l'un à l'autre
+fn acknowledge_input(msg: &mut ServerMsg, client: &Client) {
+    if let ServerMsg::Playing(_, _, tick) | ServerMsg::Delta(_, _, tick) = msg {
+        tick.last_input = client.last_input;
+    }
+}
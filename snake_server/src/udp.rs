@@ -0,0 +1,119 @@
+use crate::log;
+use crate::room::RoomEvent;
+use rand::Rng;
+use snake_protocol::*;
+use std::collections::HashMap;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+// Salon et index du client à qui appartient une clé
+struct Route {
+    room: Sender<RoomEvent>,
+    id: usize,
+    encoding: Encoding,
+}
+
+// Socket UDP du serveur, partagé par tous les salons. Les datagrammes des
+// clients commencent par la clé reçue lors du handshake
+pub struct UdpChannel {
+    socket: UdpSocket,
+    routes: Mutex<HashMap<u64, Route>>,
+}
+
+// Canal UDP d'un client, sa clé n'est plus valable quand il est détruit
+pub struct UdpLink {
+    channel: Arc<UdpChannel>,
+    pub key: u64,
+    // Adresse d'où viennent les datagrammes du client, inconnue tant
+    // qu'il n'en a envoyé aucun
+    pub addr: Option<SocketAddr>,
+}
+
+impl UdpChannel {
+    // Écoute sur la même adresse que les connexions TCP
+    pub fn bind(addrs: &[SocketAddr]) -> io::Result<Arc<UdpChannel>> {
+        let channel = Arc::new(UdpChannel {
+            socket: UdpSocket::bind(addrs)?,
+            routes: Mutex::new(HashMap::new()),
+        });
+        let listener = channel.clone();
+        thread::spawn(move || listener.listen());
+        Ok(channel)
+    }
+
+    // Donne une nouvelle clé à un client du salon
+    pub fn link(
+        channel: &Arc<UdpChannel>,
+        room: Sender<RoomEvent>,
+        id: usize,
+        encoding: Encoding,
+    ) -> UdpLink {
+        let mut routes = channel.routes.lock().unwrap();
+        let mut key = rand::thread_rng().gen();
+        while routes.contains_key(&key) {
+            key = rand::thread_rng().gen();
+        }
+        routes.insert(key, Route { room, id, encoding });
+        UdpLink {
+            channel: channel.clone(),
+            key,
+            addr: None,
+        }
+    }
+
+    // Transmet les commandes reçues au salon du client. Les datagrammes
+    // sans clé valable sont ignorés
+    fn listen(&self) {
+        let mut buffer = vec![0; MAX_DATAGRAM_SIZE];
+        loop {
+            let (len, addr) = match self.socket.recv_from(&mut buffer) {
+                Ok(received) => received,
+                Err(e) => {
+                    log!("Could not receive a datagram: {}", e);
+                    continue;
+                }
+            };
+            let (key, inputs) = match split_client_datagram(&buffer[..len]) {
+                Some(datagram) => datagram,
+                None => continue,
+            };
+            let routes = self.routes.lock().unwrap();
+            if let Some(route) = routes.get(&key) {
+                match route.encoding.decode(inputs) {
+                    Ok(inputs) => {
+                        let _ = route.room.send(RoomEvent::Datagram(route.id, addr, inputs));
+                    }
+                    Err(e) => {
+                        log!("Invalid datagram from {}: {}", addr, e);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl UdpLink {
+    // Envoie un message par UDP. Retourne false s'il doit passer par TCP:
+    // adresse du client encore inconnue ou message trop grand
+    pub fn send(&self, msg: &ServerMsg, encoding: Encoding) -> bool {
+        let addr = match self.addr {
+            Some(addr) => addr,
+            None => return false,
+        };
+        match encoding.encode(msg) {
+            Ok(datagram) if datagram.len() <= MAX_DATAGRAM_SIZE => {
+                self.channel.socket.send_to(&datagram, addr).is_ok()
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Drop for UdpLink {
+    fn drop(&mut self) {
+        self.channel.routes.lock().unwrap().remove(&self.key);
+    }
+}
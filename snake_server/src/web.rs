@@ -0,0 +1,205 @@
+use crate::log;
+use snake_protocol::*;
+use std::io::{self, Read, Write};
+use std::net::{Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::mpsc::{channel, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+use tungstenite::Message;
+
+// Client web, la version du protocole et les longueurs maximales des
+// messages du chat et des surnoms sont ajoutées à l'envoi de la page
+const INDEX_HTML: &str = include_str!("../web/index.html");
+// Taille maximale de l'en-tête d'une requête HTTP
+const MAX_REQUEST_SIZE: usize = 8 * 1024;
+// Délai pour recevoir l'en-tête d'une requête
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+// Les messages du serveur de jeu sont relayés entre deux lectures du navigateur
+const RELAY_PERIOD: Duration = Duration::from_millis(10);
+
+// Passerelle pour les navigateurs: sert la page du client web et relaie
+// les messages des WebSockets vers le serveur de jeu à l'adresse _server_
+pub fn serve(listener: TcpListener, server: SocketAddr) {
+    let server = loopback(server);
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                thread::spawn(move || handle_request(stream, server));
+            }
+            Err(e) => {
+                log!("Web connection failed: {}", e);
+            }
+        }
+    }
+}
+
+// Le serveur de jeu écoute peut-être sur toutes les interfaces,
+// la passerelle s'y connecte depuis la même machine
+fn loopback(addr: SocketAddr) -> SocketAddr {
+    match addr {
+        SocketAddr::V4(v4) if v4.ip().is_unspecified() => {
+            SocketAddr::from((Ipv4Addr::LOCALHOST, addr.port()))
+        }
+        SocketAddr::V6(v6) if v6.ip().is_unspecified() => {
+            SocketAddr::from((Ipv6Addr::LOCALHOST, addr.port()))
+        }
+        _ => addr,
+    }
+}
+
+fn handle_request(stream: TcpStream, server: SocketAddr) {
+    let request = match peek_request(&stream) {
+        Ok(request) => request,
+        Err(e) => {
+            log!("Invalid web request: {}", e);
+            return;
+        }
+    };
+    if is_websocket(&request) {
+        relay(stream, server);
+    } else {
+        serve_page(stream, &request);
+    }
+}
+
+// Lit l'en-tête de la requête sans le retirer du flux, tungstenite
+// doit pouvoir le relire pour accepter une WebSocket
+fn peek_request(stream: &TcpStream) -> io::Result<String> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let deadline = Instant::now() + REQUEST_TIMEOUT;
+    let mut buffer = vec![0; MAX_REQUEST_SIZE];
+    loop {
+        let len = stream.peek(&mut buffer)?;
+        let request = String::from_utf8_lossy(&buffer[..len]);
+        if request.contains("\r\n\r\n") {
+            stream.set_read_timeout(None)?;
+            return Ok(request.into_owned());
+        }
+        if len == 0 || len == MAX_REQUEST_SIZE || Instant::now() >= deadline {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "incomplete HTTP request",
+            ));
+        }
+        // L'en-tête arrive en plusieurs paquets
+        thread::sleep(RELAY_PERIOD);
+    }
+}
+
+fn is_websocket(request: &str) -> bool {
+    request.lines().any(|line| {
+        let line = line.to_ascii_lowercase();
+        line.starts_with("upgrade:") && line.contains("websocket")
+    })
+}
+
+// Seule la page du client est servie
+fn serve_page(mut stream: TcpStream, request: &str) {
+    let mut buffer = vec![0; MAX_REQUEST_SIZE];
+    let _ = stream.read(&mut buffer);
+    let path = request.split_whitespace().nth(1).unwrap_or("/");
+    let path = path.split('?').next().unwrap_or(path);
+    let response = match path {
+        "/" | "/index.html" => {
+            let page = INDEX_HTML
+                .replace("{{PROTOCOL_VERSION}}", &PROTOCOL_VERSION.to_string())
+                .replace("{{MAX_CHAT_LEN}}", &MAX_CHAT_LEN.to_string())
+                .replace("{{MAX_NAME_LEN}}", &MAX_NAME_LEN.to_string());
+            format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\n\
+                 Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                page.len(),
+                page
+            )
+        }
+        _ => {
+            String::from("HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+        }
+    };
+    let _ = stream.write_all(response.as_bytes());
+    let _ = stream.shutdown(Shutdown::Both);
+}
+
+// Chaque WebSocket a sa propre connexion au serveur de jeu: un message
+// de la WebSocket est une frame de la connexion, dans les deux sens
+fn relay(stream: TcpStream, server: SocketAddr) {
+    let browser = stream
+        .peer_addr()
+        .map_or_else(|_| String::from("unknown"), |addr| addr.to_string());
+    let mut websocket = match tungstenite::accept(stream) {
+        Ok(websocket) => websocket,
+        Err(e) => {
+            log!("WebSocket handshake with {} failed: {}", browser, e);
+            return;
+        }
+    };
+    let mut game = match TcpStream::connect(server) {
+        Ok(game) => game,
+        Err(e) => {
+            log!("Could not relay {} to the game server: {}", browser, e);
+            let _ = websocket.close(None);
+            return;
+        }
+    };
+    log!("Web client {} connected", browser);
+
+    // Les messages du serveur de jeu sont lus dans un thread
+    let (sender, frames) = channel();
+    let mut reader = game.try_clone().expect("Could not clone game stream");
+    thread::spawn(move || {
+        while let Ok(frame) = read_frame(&mut reader) {
+            if sender.send(frame).is_err() {
+                return;
+            }
+        }
+    });
+    if websocket
+        .get_ref()
+        .set_read_timeout(Some(RELAY_PERIOD))
+        .is_err()
+    {
+        return;
+    }
+    loop {
+        loop {
+            match frames.try_recv() {
+                Ok(frame) => {
+                    let msg = match String::from_utf8(frame) {
+                        Ok(json) => Message::Text(json),
+                        Err(e) => Message::Binary(e.into_bytes()),
+                    };
+                    if websocket.send(msg).is_err() {
+                        let _ = game.shutdown(Shutdown::Both);
+                        return;
+                    }
+                }
+                Err(TryRecvError::Empty) => break,
+                // Le serveur de jeu a fermé la connexion
+                Err(TryRecvError::Disconnected) => {
+                    let _ = websocket.close(None);
+                    let _ = websocket.flush();
+                    log!("Web client {} disconnected", browser);
+                    return;
+                }
+            }
+        }
+        let sent = match websocket.read() {
+            Ok(Message::Text(json)) => write_frame(&mut game, json.as_bytes()),
+            Ok(Message::Binary(bytes)) => write_frame(&mut game, &bytes),
+            Ok(Message::Close(_)) => break,
+            // Les pings de la WebSocket sont traités par tungstenite
+            Ok(_) => Ok(()),
+            Err(tungstenite::Error::Io(e))
+                if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut =>
+            {
+                Ok(())
+            }
+            Err(_) => break,
+        };
+        if sent.is_err() {
+            break;
+        }
+    }
+    log!("Web client {} disconnected", browser);
+    let _ = game.shutdown(Shutdown::Both);
+}